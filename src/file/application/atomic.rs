@@ -0,0 +1,215 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::file::{ConflictOutcome, ConflictPolicy, PathUtil};
+
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    /// Write to a temp file in the same dir, fsync and rename over the target.
+    /// Readers see the old or the new contents, never a half-written file.
+    pub atomic: bool,
+    /// Keep the previous contents as `<path>.bak`
+    pub backup: bool,
+    /// When the target already exists (`OverwriteIfNewer` is the same as `Overwrite`, new data is always newer)
+    pub conflict: ConflictPolicy,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            atomic: false,
+            backup: false,
+            conflict: ConflictPolicy::Overwrite,
+        }
+    }
+}
+
+impl WriteOptions {
+    /// atomic + durable
+    pub fn atomic() -> Self {
+        WriteOptions {
+            atomic: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// `write` with options (atomic, backup, conflict policy). Returns what was done.
+pub fn write_with(path: PathBuf, data: &[u8], options: &WriteOptions) -> Result<ConflictOutcome> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    // ディレクトリ作成(create_dir_all は既存でもエラーにならない)
+    fs::create_dir_all(&dir)?;
+
+    let (path, outcome) = match options.conflict.resolve(&path, None)? {
+        (Some(path), outcome) => (path, outcome),
+        (None, outcome) => return Ok(outcome),
+    };
+    let exists = outcome == ConflictOutcome::Overwritten;
+    // 確認後に作られた場合も、上書きしてよいのは Overwrite 系だけ
+    let overwrite = exists
+        || (outcome == ConflictOutcome::Done
+            && matches!(
+                options.conflict,
+                ConflictPolicy::Overwrite | ConflictPolicy::OverwriteIfNewer
+            ));
+
+    if !options.atomic {
+        if exists && options.backup {
+            // その場で書き換えるので、元のファイルは rename で退避
+            fs::rename(&path, backup_path(&path))?;
+        }
+        let mut file = if overwrite {
+            File::create(&path)?
+        } else {
+            File::options().write(true).create_new(true).open(&path)?
+        };
+        file.write_all(data)?;
+        return Ok(outcome);
+    }
+
+    let (temp, mut file) = create_temp(&dir, &path)?;
+    let result = (|| -> Result<()> {
+        file.write_all(data)?;
+        if let Ok(meta) = fs::metadata(&path) {
+            // 置き換えるファイルのパーミッションを引き継ぐ
+            file.set_permissions(meta.permissions())?;
+        }
+        file.sync_all()?;
+        drop(file);
+
+        if exists && options.backup {
+            // 置き換えまで元のファイルを残すため、rename ではなく link/copy
+            let backup = backup_path(&path);
+            let _ = fs::remove_file(&backup);
+            if fs::hard_link(&path, &backup).is_err() {
+                fs::copy(&path, &backup)?;
+            }
+        }
+
+        if overwrite {
+            fs::rename(&temp, &path)?;
+        } else {
+            // hard_link は既存のファイルを上書きしない
+            fs::hard_link(&temp, &path)
+                .with_context(|| format!("Already exists. Path: {}", path.to_string_ex()))?;
+            fs::remove_file(&temp)?;
+        }
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+
+    sync_dir(&dir)?;
+    Ok(outcome)
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+fn create_temp(dir: &Path, path: &Path) -> Result<(PathBuf, File)> {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    for count in 0.. {
+        let temp = dir.join(format!(".{}.a2tmp{}", name, count));
+        match File::options().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    unreachable!()
+}
+
+// rename をディスクに反映するため、ディレクトリも fsync
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_with() {
+        let test_dir = "test_write_with";
+        let path = PathBuf::from(format!("{}/sub/settings.json", test_dir));
+
+        write_with(path.clone(), b"v1", &WriteOptions::atomic()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"v1");
+
+        let options = WriteOptions {
+            backup: true,
+            ..WriteOptions::atomic()
+        };
+        write_with(path.clone(), b"v2", &options).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"v2");
+        assert_eq!(fs::read(backup_path(&path)).unwrap(), b"v1");
+
+        // non atomic backup
+        let options = WriteOptions {
+            backup: true,
+            ..Default::default()
+        };
+        write_with(path.clone(), b"v3", &options).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"v3");
+        assert_eq!(fs::read(backup_path(&path)).unwrap(), b"v2");
+
+        // no overwrite
+        for atomic in [true, false] {
+            let options = WriteOptions {
+                atomic,
+                conflict: ConflictPolicy::Fail,
+                ..Default::default()
+            };
+            assert!(write_with(path.clone(), b"v4", &options).is_err());
+            assert_eq!(fs::read(&path).unwrap(), b"v3");
+
+            let options = WriteOptions {
+                conflict: ConflictPolicy::Skip,
+                ..options
+            };
+            let outcome = write_with(path.clone(), b"v4", &options).unwrap();
+            assert_eq!(outcome, ConflictOutcome::Skipped);
+            assert_eq!(fs::read(&path).unwrap(), b"v3");
+        }
+
+        // no temp files left
+        let names: Vec<String> = fs::read_dir(format!("{}/sub", test_dir))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names.len(), 2);
+
+        let options = WriteOptions {
+            conflict: ConflictPolicy::KeepBoth,
+            ..WriteOptions::atomic()
+        };
+        let outcome = write_with(path.clone(), b"v5", &options).unwrap();
+        let kept = path.with_file_name("settings (1).json");
+        assert_eq!(outcome, ConflictOutcome::KeptBoth(kept.clone()));
+        assert_eq!(fs::read(&kept).unwrap(), b"v5");
+        assert_eq!(fs::read(&path).unwrap(), b"v3");
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+}
@@ -0,0 +1,616 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::file::{
+    ConflictOutcome, ConflictPolicy, FileInfo, ItemOutcome, PathUtil, SortBy, SortKey, SortUtil,
+};
+use crate::time::Timestamp;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RenameItem {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl RenameItem {
+    pub fn is_changed(&self) -> bool {
+        self.from != self.to
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RenameConflict {
+    /// Several entries get the same new name
+    Duplicate { to: PathBuf, from: Vec<PathBuf> },
+    /// The new name is used by a file that is not renamed in this batch
+    Exists { from: PathBuf, to: PathBuf },
+    /// Empty name, separator in the name, regex did not match, etc...
+    Invalid { from: PathBuf, reason: String },
+}
+
+/// Result of `BatchRename::plan`. Check `conflicts` (preview) before `apply`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RenamePlan {
+    /// In natural order of the original paths
+    pub items: Vec<RenameItem>,
+    pub conflicts: Vec<RenameConflict>,
+    /// Chains of renames onto each other (a -> b, b -> a). `apply` handles them with temp names.
+    pub cycles: Vec<Vec<PathBuf>>,
+    /// Existing files which `apply` replaces (set by `resolve`)
+    #[serde(default)]
+    pub overwrite: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Text(String),
+    Name,
+    Ext,
+    FileName,
+    Index(usize),
+    Date,
+    Time,
+    Capture(usize),
+}
+
+/// Rename planner with a template.
+///
+/// Tokens: `{name}` (without extension), `{ext}`, `{file_name}`, `{index}` / `{index:3}` (natural order, zero padded),
+/// `{date}` (YYYY-MM-DD) / `{time}` (hhmmss) of the modified time (local time), `{0}`..`{9}` (regex captures of the
+/// file name), `{{` / `}}`.
+///
+/// `BatchRename::new("{index:3}.{ext}")?.plan(&infos)` renames scanned pages into `001.jpg`, `002.jpg`, ...
+#[derive(Debug, Clone)]
+pub struct BatchRename {
+    tokens: Vec<Token>,
+    regex: Option<Regex>,
+    start: usize,
+}
+
+impl BatchRename {
+    pub fn new(template: &str) -> Result<Self> {
+        Ok(BatchRename {
+            tokens: parse_template(template)?,
+            regex: None,
+            start: 1,
+        })
+    }
+
+    /// Regex applied to the file name for `{0}`..`{9}`
+    pub fn regex(mut self, pattern: &str) -> Result<Self> {
+        self.regex = Some(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// First value of `{index}` (default 1)
+    pub fn start(mut self, start: usize) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub fn plan(&self, infos: &[FileInfo]) -> RenamePlan {
+        let mut infos: Vec<FileInfo> = infos.to_vec();
+        infos.sort_by_keys(&[SortBy::asc(SortKey::Path)]);
+
+        let mut plan = RenamePlan::default();
+        for (i, info) in infos.iter_mut().enumerate() {
+            match self.render(info, self.start + i) {
+                Ok(name) => plan.items.push(RenameItem {
+                    from: info.path.clone(),
+                    to: info.dir.join(name),
+                }),
+                Err(reason) => {
+                    plan.conflicts.push(RenameConflict::Invalid {
+                        from: info.path.clone(),
+                        reason,
+                    });
+                    // 名前を変えないものとして扱う
+                    plan.items.push(RenameItem {
+                        from: info.path.clone(),
+                        to: info.path.clone(),
+                    });
+                }
+            }
+        }
+
+        let invalid = std::mem::take(&mut plan.conflicts);
+        let mut plan = RenamePlan::from_items(plan.items);
+        plan.conflicts.splice(0..0, invalid);
+        plan
+    }
+
+    fn render(&self, info: &mut FileInfo, index: usize) -> std::result::Result<String, String> {
+        let uses_date = self
+            .tokens
+            .iter()
+            .any(|token| matches!(token, Token::Date | Token::Time));
+        if uses_date && info.meta.is_none() {
+            info.load_meta();
+        }
+        let info = &*info;
+
+        let (stem, ext) = split_name(&info.file_name);
+        let captures = match &self.regex {
+            Some(regex) => Some(
+                regex
+                    .captures(&info.file_name)
+                    .ok_or_else(|| "Regex did not match".to_string())?,
+            ),
+            None => None,
+        };
+
+        let mut name = String::new();
+        for token in &self.tokens {
+            match token {
+                Token::Text(text) => name.push_str(text),
+                Token::Name => name.push_str(stem),
+                Token::Ext => name.push_str(ext),
+                Token::FileName => name.push_str(&info.file_name),
+                Token::Index(width) => name.push_str(&format!("{:0width$}", index, width = width)),
+                Token::Date | Token::Time => {
+                    let modified = info.meta.as_ref().map_or(0, |meta| meta.modified);
+                    let datetime = Timestamp::to_local_string(modified);
+                    let (date, time) = datetime.split_once('T').unwrap_or_default();
+                    match token {
+                        Token::Date => name.push_str(date),
+                        _ => name.push_str(&time.replace(':', "")),
+                    }
+                }
+                Token::Capture(i) => {
+                    let captures = captures
+                        .as_ref()
+                        .ok_or_else(|| format!("No regex for {{{}}}", i))?;
+                    name.push_str(captures.get(*i).map_or("", |m| m.as_str()));
+                }
+            }
+        }
+
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(format!("Invalid name: {:?}", name));
+        }
+        if name.contains('/') || name.contains(std::path::MAIN_SEPARATOR) {
+            return Err(format!("Separator in name: {}", name));
+        }
+        Ok(name)
+    }
+}
+
+impl RenamePlan {
+    /// Plan from old -> new pairs (conflicts and cycles are detected)
+    pub fn from_items(items: Vec<RenameItem>) -> Self {
+        RenamePlan {
+            conflicts: find_collisions(&items),
+            cycles: find_cycles(&items),
+            items,
+            overwrite: Vec::new(),
+        }
+    }
+
+    /// Settle `Exists` conflicts by `policy` (other conflicts are kept) for preview.
+    /// Returns the new plan and the outcome of each item to be renamed or skipped.
+    pub fn resolve(&self, policy: ConflictPolicy) -> Result<(RenamePlan, Vec<ItemOutcome>)> {
+        let exists: HashSet<&Path> = self
+            .conflicts
+            .iter()
+            .filter_map(|conflict| match conflict {
+                RenameConflict::Exists { from, .. } => Some(from.as_path()),
+                _ => None,
+            })
+            .collect();
+
+        let mut items: Vec<RenameItem> = Vec::new();
+        let mut overwrite = self.overwrite.clone();
+        let mut outcomes: Vec<ItemOutcome> = Vec::new();
+        for item in &self.items {
+            let mut item = item.clone();
+            let mut outcome = ConflictOutcome::Done;
+            if exists.contains(item.from.as_path()) {
+                let modified = fs::symlink_metadata(&item.from)
+                    .and_then(|meta| meta.modified())
+                    .ok();
+                let (to, resolved) = policy.resolve(&item.to, modified)?;
+                match to {
+                    Some(to) if resolved == ConflictOutcome::Overwritten => overwrite.push(to),
+                    Some(to) => item.to = to,
+                    None => {
+                        outcomes.push(ItemOutcome {
+                            from: item.from.clone(),
+                            to: item.to.clone(),
+                            outcome: resolved,
+                        });
+                        // 名前を変えない
+                        item.to = item.from.clone();
+                        items.push(item);
+                        continue;
+                    }
+                }
+                outcome = resolved;
+            }
+            if item.is_changed() {
+                outcomes.push(ItemOutcome {
+                    from: item.from.clone(),
+                    to: item.to.clone(),
+                    outcome,
+                });
+            }
+            items.push(item);
+        }
+
+        let mut plan = RenamePlan {
+            overwrite,
+            ..RenamePlan::from_items(items)
+        };
+        let invalid = self
+            .conflicts
+            .iter()
+            .filter(|conflict| matches!(conflict, RenameConflict::Invalid { .. }))
+            .cloned();
+        plan.conflicts.splice(0..0, invalid);
+        let conflicts = std::mem::take(&mut plan.conflicts);
+        plan.conflicts = conflicts
+            .into_iter()
+            .filter(|conflict| !plan.is_overwrite(conflict))
+            .collect();
+        Ok((plan, outcomes))
+    }
+
+    /// `resolve` + `apply`
+    pub fn apply_with(&self, policy: ConflictPolicy) -> Result<Vec<ItemOutcome>> {
+        let (plan, outcomes) = self.resolve(policy)?;
+        plan.apply()?;
+        Ok(outcomes)
+    }
+
+    fn is_overwrite(&self, conflict: &RenameConflict) -> bool {
+        matches!(conflict, RenameConflict::Exists { to, .. } if self.overwrite.contains(to))
+    }
+
+    /// No conflicts (cycles are fine)
+    pub fn is_valid(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    /// Items whose names change
+    pub fn changes(&self) -> impl Iterator<Item = &RenameItem> {
+        self.items.iter().filter(|item| item.is_changed())
+    }
+
+    /// Rename in two phases (all sources to temp names, then to the new names) so that swaps and chains are safe.
+    /// When it fails on the way, finished renames are rolled back as far as possible.
+    pub fn apply(&self) -> Result<()> {
+        if !self.is_valid() {
+            return Err(anyhow!("Plan has conflicts. {:?}", self.conflicts));
+        }
+        let changes: Vec<&RenameItem> = self.changes().collect();
+        // 計画後に状況が変わっていないか
+        if let Some(conflict) = find_collisions(&self.items)
+            .into_iter()
+            .find(|conflict| !self.is_overwrite(conflict))
+        {
+            return Err(anyhow!("Plan is outdated. {:?}", conflict));
+        }
+        for item in &changes {
+            if fs::symlink_metadata(&item.from).is_err() {
+                return Err(anyhow!(
+                    "Source path does not exist. Path: {}",
+                    item.from.to_string_ex()
+                ));
+            }
+        }
+
+        // phase 1: from -> temp
+        let mut temps: Vec<PathBuf> = Vec::new();
+        for (i, item) in changes.iter().enumerate() {
+            let temp = temp_path(&item.from, i);
+            if let Err(e) = fs::rename(&item.from, &temp) {
+                rollback(&changes, &temps, 0);
+                return Err(anyhow!(
+                    "Failed to rename. Path: {}, {}",
+                    item.from.to_string_ex(),
+                    e
+                ));
+            }
+            temps.push(temp);
+        }
+
+        // phase 2: temp -> to
+        for (i, item) in changes.iter().enumerate() {
+            if let Err(e) = fs::rename(&temps[i], &item.to) {
+                rollback(&changes, &temps, i);
+                return Err(anyhow!(
+                    "Failed to rename. Path: {}, {}",
+                    item.to.to_string_ex(),
+                    e
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// 途中で失敗したら、to になったもの (done 件) を temp に戻してから、temp を from に戻す
+fn rollback(changes: &[&RenameItem], temps: &[PathBuf], done: usize) {
+    for i in 0..done {
+        let _ = fs::rename(&changes[i].to, &temps[i]);
+    }
+    for (i, temp) in temps.iter().enumerate() {
+        let _ = fs::rename(temp, &changes[i].from);
+    }
+}
+
+fn temp_path(from: &Path, index: usize) -> PathBuf {
+    let dir = from.parent().unwrap_or(Path::new(""));
+    let mut count = 0;
+    loop {
+        let temp = dir.join(format!(".a2rename{}_{}", index, count));
+        if fs::symlink_metadata(&temp).is_err() {
+            return temp;
+        }
+        count += 1;
+    }
+}
+
+// "a.tar.gz" -> ("a.tar", "gz"), ".hidden" -> (".hidden", "")
+pub(crate) fn split_name(file_name: &str) -> (&str, &str) {
+    match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, ext),
+        _ => (file_name, ""),
+    }
+}
+
+fn parse_template(template: &str) -> Result<Vec<Token>> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inner.push(c),
+                        None => return Err(anyhow!("Unclosed token. Template: {}", template)),
+                    }
+                }
+                if !text.is_empty() {
+                    tokens.push(Token::Text(std::mem::take(&mut text)));
+                }
+                tokens.push(parse_token(&inner)?);
+            }
+            '}' => return Err(anyhow!("Unexpected '}}'. Template: {}", template)),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    Ok(tokens)
+}
+
+fn parse_token(inner: &str) -> Result<Token> {
+    let (key, arg) = match inner.split_once(':') {
+        Some((key, arg)) => (key, Some(arg)),
+        None => (inner, None),
+    };
+    let token = match key {
+        "name" => Token::Name,
+        "ext" => Token::Ext,
+        "file_name" => Token::FileName,
+        "date" => Token::Date,
+        "time" => Token::Time,
+        "index" => Token::Index(match arg {
+            Some(width) => width
+                .parse()
+                .map_err(|_| anyhow!("Invalid width. Token: {{{}}}", inner))?,
+            None => 0,
+        }),
+        key => match key.parse() {
+            Ok(i) => Token::Capture(i),
+            Err(_) => return Err(anyhow!("Unknown token. Token: {{{}}}", inner)),
+        },
+    };
+    Ok(token)
+}
+
+fn find_collisions(items: &[RenameItem]) -> Vec<RenameConflict> {
+    let mut conflicts: Vec<RenameConflict> = Vec::new();
+
+    let mut by_to: HashMap<&Path, Vec<&Path>> = HashMap::new();
+    let mut order: Vec<&Path> = Vec::new();
+    for item in items {
+        let froms = by_to.entry(item.to.as_path()).or_default();
+        if froms.is_empty() {
+            order.push(item.to.as_path());
+        }
+        froms.push(item.from.as_path());
+    }
+    for to in order {
+        let froms = &by_to[to];
+        if froms.len() > 1 {
+            conflicts.push(RenameConflict::Duplicate {
+                to: to.to_path_buf(),
+                from: froms.iter().map(|p| p.to_path_buf()).collect(),
+            });
+        }
+    }
+
+    // 既存のファイルと重なる (ただし、この batch で別名になるものは除く)
+    let sources: HashSet<&Path> = items.iter().map(|item| item.from.as_path()).collect();
+    for item in items.iter().filter(|item| item.is_changed()) {
+        if !sources.contains(item.to.as_path()) && fs::symlink_metadata(&item.to).is_ok() {
+            conflicts.push(RenameConflict::Exists {
+                from: item.from.clone(),
+                to: item.to.clone(),
+            });
+        }
+    }
+    conflicts
+}
+
+fn find_cycles(items: &[RenameItem]) -> Vec<Vec<PathBuf>> {
+    let next: HashMap<&Path, &Path> = items
+        .iter()
+        .filter(|item| item.is_changed())
+        .map(|item| (item.from.as_path(), item.to.as_path()))
+        .collect();
+
+    let mut cycles: Vec<Vec<PathBuf>> = Vec::new();
+    let mut seen: HashSet<&Path> = HashSet::new();
+    for item in items.iter().filter(|item| item.is_changed()) {
+        let start = item.from.as_path();
+        if seen.contains(start) {
+            continue;
+        }
+        // start から辿って start に戻れば循環
+        let mut chain: Vec<&Path> = vec![start];
+        let mut current = start;
+        while let Some(to) = next.get(current) {
+            if *to == start {
+                seen.extend(chain.iter());
+                cycles.push(chain.iter().map(|p| p.to_path_buf()).collect());
+                break;
+            }
+            if chain.contains(to) {
+                break;
+            }
+            chain.push(to);
+            current = to;
+        }
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::OptionPathUtil;
+
+    fn infos(dir: &str, names: &[&str]) -> Vec<FileInfo> {
+        fs::create_dir_all(dir).unwrap();
+        names
+            .iter()
+            .map(|name| {
+                let path = format!("{}/{}", dir, name);
+                fs::write(&path, name.as_bytes()).unwrap();
+                FileInfo::from_str(&path)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_batch_rename() {
+        let test_dir = "test_batch_rename";
+        let infos = infos(test_dir, &["scan10.JPG", "scan2.jpg", "scan1.jpg"]);
+
+        let plan = BatchRename::new("{index:3}.{ext}").unwrap().plan(&infos);
+        assert!(plan.is_valid());
+        let names: Vec<String> = plan
+            .items
+            .iter()
+            .map(|item| item.to.file_name().to_string_ex())
+            .collect();
+        assert_eq!(names, ["001.jpg", "002.jpg", "003.JPG"]);
+        assert_eq!(
+            plan.items[0].from,
+            PathBuf::from(test_dir).join("scan1.jpg")
+        );
+        plan.apply().unwrap();
+        assert_eq!(
+            fs::read(format!("{}/003.JPG", test_dir)).unwrap(),
+            b"scan10.JPG"
+        );
+
+        // regex captures and escapes
+        let infos = crate::file::read_dir(test_dir).unwrap();
+        let plan = BatchRename::new("{{p}}{1}_{name}")
+            .unwrap()
+            .regex(r"^0*(\d+)")
+            .unwrap()
+            .plan(&infos);
+        assert_eq!(plan.items[0].to, PathBuf::from(test_dir).join("{p}1_001"));
+
+        // date
+        let plan = BatchRename::new("{date}_{time}").unwrap().plan(&infos[..1]);
+        let name = plan.items[0].to.file_name().to_string_ex();
+        assert_eq!(name.len(), "2024-01-01_000000".len());
+        // local time (same as the clock of the user)
+        let modified = fs::metadata(&infos[0].path).unwrap().modified().unwrap();
+        let local = Timestamp::to_local_string(Timestamp::from_system_time(modified));
+        assert_eq!(name, local.replace('T', "_").replace(':', ""));
+
+        // collisions
+        let plan = BatchRename::new("same.jpg").unwrap().plan(&infos);
+        assert!(
+            matches!(&plan.conflicts[0], RenameConflict::Duplicate { from, .. } if from.len() == 3)
+        );
+        assert!(plan.apply().is_err());
+        let plan = BatchRename::new("002.jpg").unwrap().plan(&infos[..1]);
+        assert!(matches!(plan.conflicts[0], RenameConflict::Exists { .. }));
+        assert!(BatchRename::new("{unknown}").is_err());
+
+        // conflict policies
+        let (skipped, outcomes) = plan.resolve(ConflictPolicy::Skip).unwrap();
+        assert!(skipped.is_valid());
+        assert_eq!(skipped.changes().count(), 0);
+        assert_eq!(outcomes[0].outcome, ConflictOutcome::Skipped);
+        assert!(plan.resolve(ConflictPolicy::Fail).is_err());
+        let outcomes = plan.apply_with(ConflictPolicy::KeepBoth).unwrap();
+        assert_eq!(outcomes[0].to, PathBuf::from(test_dir).join("002 (1).jpg"));
+        let source = crate::file::read_dir(test_dir)
+            .unwrap()
+            .into_iter()
+            .find(|info| info.file_name != "002.jpg")
+            .unwrap();
+        let data = fs::read(&source.path).unwrap();
+        let plan = BatchRename::new("002.jpg").unwrap().plan(&[source]);
+        let outcomes = plan.apply_with(ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(outcomes[0].outcome, ConflictOutcome::Overwritten);
+        assert_eq!(fs::read(format!("{}/002.jpg", test_dir)).unwrap(), data);
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_batch_rename_cycle() {
+        let test_dir = "test_batch_rename_cycle";
+        let infos = infos(test_dir, &["1.txt", "2.txt", "3.txt"]);
+
+        // 1 -> 2, 2 -> 3, 3 -> 1 (rotate)
+        let path = |name: &str| PathBuf::from(test_dir).join(name);
+        let items = infos
+            .iter()
+            .map(|info| {
+                let index: usize = info.file_name[..1].parse().unwrap();
+                RenameItem {
+                    from: info.path.clone(),
+                    to: path(&format!("{}.txt", index % 3 + 1)),
+                }
+            })
+            .collect();
+        let plan = RenamePlan::from_items(items);
+        assert!(plan.is_valid());
+        assert_eq!(plan.cycles.len(), 1);
+        assert_eq!(plan.cycles[0].len(), 3);
+
+        plan.apply().unwrap();
+        assert_eq!(fs::read(format!("{}/2.txt", test_dir)).unwrap(), b"1.txt");
+        assert_eq!(fs::read(format!("{}/1.txt", test_dir)).unwrap(), b"3.txt");
+        assert_eq!(fs::read_dir(test_dir).unwrap().count(), 3);
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::file::{read_dir, write_with, zip_util::ZipUtil, FileInfo, WriteOptions};
+
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum ListingKind {
+    Dir,
+    Zip,
+}
+
+impl ListingKind {
+    fn key(&self, path: &str) -> String {
+        match self {
+            ListingKind::Dir => format!("dir:{}", path),
+            ListingKind::Zip => format!("zip:{}", path),
+        }
+    }
+}
+
+// 元のディレクトリ/zip がこれと変わっていたら無効
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+struct SourceStamp {
+    modified: u64, // nanos
+    size: u64,
+}
+
+impl SourceStamp {
+    fn read(path: &str) -> Result<SourceStamp> {
+        let meta = std::fs::metadata(path)?;
+        let modified = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Ok(SourceStamp {
+            modified,
+            size: meta.len(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CacheRecord {
+    file: String,
+    stamp: SourceStamp,
+    bytes: u64,
+    last_used: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct CacheIndex {
+    records: HashMap<String, CacheRecord>,
+    // LRU 用の連番
+    clock: u64,
+}
+
+/// On-disk cache of `read_dir` and zip listings.
+/// Entries are invalidated when the mtime/size of the dir or zip changes, and the least recently used ones are
+/// evicted over `max_bytes`.
+/// Cache hits update the LRU order only in memory, it is saved by `flush` (also on drop) or the next change.
+pub struct ListingCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: CacheIndex,
+    // index に保存していない変更 (LRU の更新) がある
+    dirty: bool,
+}
+
+impl ListingCache {
+    /// Open (or create) the cache in `dir`
+    pub fn open(dir: &str, max_bytes: u64) -> Result<ListingCache> {
+        let dir = PathBuf::from(dir);
+        std::fs::create_dir_all(&dir)?;
+        let mut cache = ListingCache {
+            dir,
+            max_bytes,
+            index: CacheIndex::default(),
+            dirty: false,
+        };
+        // 壊れた index は捨てて作り直す
+        cache.index = std::fs::read(cache.index_path())
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default();
+        Ok(cache)
+    }
+
+    /// Cached version of `read_dir`
+    pub fn read_dir(&mut self, dir: &str) -> Result<Vec<FileInfo>> {
+        self.get_or_load(ListingKind::Dir, dir, || read_dir(dir))
+    }
+
+    /// Cached version of `ZipUtil::read_file_infos` (without the archive)
+    pub fn read_zip_file_infos(&mut self, path: &str) -> Result<Vec<FileInfo>> {
+        self.get_or_load(ListingKind::Zip, path, || {
+            ZipUtil::read_file_infos(path).map(|(_, infos)| infos)
+        })
+    }
+
+    pub fn invalidate(&mut self, path: &str) -> Result<()> {
+        for kind in [ListingKind::Dir, ListingKind::Zip] {
+            self.remove(&kind.key(path));
+        }
+        self.save_index()
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        let keys: Vec<String> = self.index.records.keys().cloned().collect();
+        for key in keys {
+            self.remove(&key);
+        }
+        self.save_index()
+    }
+
+    /// Save the LRU order updated by cache hits
+    pub fn flush(&mut self) -> Result<()> {
+        if self.dirty {
+            self.save_index()?;
+        }
+        Ok(())
+    }
+
+    /// Number of cached listings
+    pub fn len(&self) -> usize {
+        self.index.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.records.is_empty()
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.index.records.values().map(|record| record.bytes).sum()
+    }
+
+    fn get_or_load(
+        &mut self,
+        kind: ListingKind,
+        path: &str,
+        load: impl FnOnce() -> Result<Vec<FileInfo>>,
+    ) -> Result<Vec<FileInfo>> {
+        let key = kind.key(path);
+        let stamp = SourceStamp::read(path).with_context(|| format!("Path: {}", path))?;
+
+        if let Some(infos) = self.get(&key, stamp) {
+            return Ok(infos);
+        }
+
+        let infos = load()?;
+        self.put(key, stamp, &infos)?;
+        Ok(infos)
+    }
+
+    fn get(&mut self, key: &str, stamp: SourceStamp) -> Option<Vec<FileInfo>> {
+        let record = self.index.records.get(key)?;
+        let infos = if record.stamp == stamp {
+            std::fs::read(self.dir.join(&record.file))
+                .ok()
+                .and_then(|json| serde_json::from_slice::<Vec<FileInfo>>(&json).ok())
+        } else {
+            None
+        };
+
+        match infos {
+            Some(infos) => {
+                self.index.clock += 1;
+                let clock = self.index.clock;
+                if let Some(record) = self.index.records.get_mut(key) {
+                    record.last_used = clock;
+                }
+                // 毎回 fsync すると読み直すより遅いので、保存は後で
+                self.dirty = true;
+                Some(infos)
+            }
+            None => {
+                // 古い or 壊れている
+                self.remove(key);
+                self.dirty = true;
+                None
+            }
+        }
+    }
+
+    fn put(&mut self, key: String, stamp: SourceStamp, infos: &[FileInfo]) -> Result<()> {
+        let json = serde_json::to_vec(infos)?;
+        let bytes = json.len() as u64;
+        if bytes > self.max_bytes {
+            // 単体で上限を超えるものは保存しない
+            return Ok(());
+        }
+
+        let file = format!("{}.json", blake3::hash(key.as_bytes()).to_hex());
+        std::fs::write(self.dir.join(&file), json)?;
+        self.index.clock += 1;
+        let record = CacheRecord {
+            file,
+            stamp,
+            bytes,
+            last_used: self.index.clock,
+        };
+        self.index.records.insert(key, record);
+
+        self.evict();
+        self.save_index()
+    }
+
+    fn evict(&mut self) {
+        while self.total_bytes() > self.max_bytes {
+            let oldest = self
+                .index
+                .records
+                .iter()
+                .min_by_key(|(_, record)| record.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => self.remove(&key),
+                None => break,
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(record) = self.index.records.remove(key) {
+            let _ = std::fs::remove_file(self.dir.join(record.file));
+        }
+    }
+
+    fn save_index(&mut self) -> Result<()> {
+        let json = serde_json::to_vec(&self.index)?;
+        write_with(self.index_path(), &json, &WriteOptions::atomic())?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join(INDEX_FILE)
+    }
+}
+
+impl Drop for ListingCache {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_cache() {
+        let test_dir = "test_listing_cache";
+        let cache_dir = "test_listing_cache_store";
+        std::fs::create_dir_all(format!("{}/a", test_dir)).unwrap();
+        std::fs::create_dir_all(format!("{}/b", test_dir)).unwrap();
+        std::fs::write(format!("{}/a/1.txt", test_dir), b"1").unwrap();
+        std::fs::write(format!("{}/b/1.txt", test_dir), b"1").unwrap();
+
+        let dir_a = format!("{}/a", test_dir);
+        let dir_b = format!("{}/b", test_dir);
+        let mut cache = ListingCache::open(cache_dir, 1024 * 1024).unwrap();
+        assert_eq!(cache.read_dir(&dir_a).unwrap().len(), 1);
+        assert_eq!(cache.len(), 1);
+
+        // survives reopening
+        drop(cache);
+        let mut cache = ListingCache::open(cache_dir, 1024 * 1024).unwrap();
+        assert_eq!(cache.len(), 1);
+        let index = std::fs::read(cache.index_path()).unwrap();
+        assert_eq!(cache.read_dir(&dir_a).unwrap().len(), 1);
+
+        // a hit is saved only by flush
+        assert!(cache.dirty);
+        assert_eq!(std::fs::read(cache.index_path()).unwrap(), index);
+        cache.flush().unwrap();
+        assert!(!cache.dirty);
+        assert_ne!(std::fs::read(cache.index_path()).unwrap(), index);
+
+        // invalidated by a change of the dir
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        std::fs::write(format!("{}/a/2.txt", test_dir), b"2").unwrap();
+        assert_eq!(cache.read_dir(&dir_a).unwrap().len(), 2);
+
+        // zip listing
+        let infos = cache.read_zip_file_infos("tests/data/sample.zip").unwrap();
+        assert!(!infos.is_empty());
+        assert_eq!(cache.len(), 2);
+
+        // LRU: only the recently used one is kept
+        let limit = cache.total_bytes();
+        drop(cache);
+        let mut cache = ListingCache::open(cache_dir, limit).unwrap();
+        cache.read_dir(&dir_a).unwrap();
+        cache.read_dir(&dir_b).unwrap();
+        assert!(cache.total_bytes() <= limit);
+        assert!(cache
+            .index
+            .records
+            .contains_key(&ListingKind::Dir.key(&dir_b)));
+        assert!(!cache
+            .index
+            .records
+            .contains_key(&ListingKind::Zip.key("tests/data/sample.zip")));
+
+        cache.clear().unwrap();
+        assert!(cache.is_empty());
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+        std::fs::remove_dir_all(cache_dir).unwrap();
+    }
+}
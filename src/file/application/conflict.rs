@@ -0,0 +1,156 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::file::batch_rename::split_name;
+use crate::file::{OptionPathUtil, PathUtil};
+
+/// What to do when the destination already exists
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Return an error
+    Fail,
+    /// Replace the destination (default). Same rules as `fs::rename`: a file replaces a file,
+    /// a dir replaces an empty dir (Unix only). Replacing a different type fails and nothing is removed.
+    #[default]
+    Overwrite,
+    /// Leave both as they are
+    Skip,
+    /// Use a free name like `name (1).jpg`
+    KeepBoth,
+    /// Replace only when the source is newer (modified time)
+    OverwriteIfNewer,
+}
+
+/// What was actually done for an item
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ConflictOutcome {
+    /// No conflict
+    Done,
+    Overwritten,
+    Skipped,
+    /// Done with another name (`ConflictPolicy::KeepBoth`)
+    KeptBoth(PathBuf),
+    /// Error of the item in batch operations
+    Failed(String),
+}
+
+/// Result of an item of batch operations
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ItemOutcome {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub outcome: ConflictOutcome,
+}
+
+impl ConflictPolicy {
+    /// Decide the destination. None when it should be skipped.
+    /// `source_modified` is used by `OverwriteIfNewer` (None: the source is new data, always newer).
+    pub fn resolve(
+        &self,
+        to: &Path,
+        source_modified: Option<SystemTime>,
+    ) -> Result<(Option<PathBuf>, ConflictOutcome)> {
+        let Ok(meta) = fs::symlink_metadata(to) else {
+            return Ok((Some(to.to_path_buf()), ConflictOutcome::Done));
+        };
+
+        match self {
+            ConflictPolicy::Fail => Err(anyhow!("Already exists. Path: {}", to.to_string_ex())),
+            ConflictPolicy::Overwrite => Ok((Some(to.to_path_buf()), ConflictOutcome::Overwritten)),
+            ConflictPolicy::Skip => Ok((None, ConflictOutcome::Skipped)),
+            ConflictPolicy::KeepBoth => {
+                let free = free_path(to);
+                Ok((Some(free.clone()), ConflictOutcome::KeptBoth(free)))
+            }
+            ConflictPolicy::OverwriteIfNewer => {
+                let is_newer = match (source_modified, meta.modified()) {
+                    (Some(source), Ok(dest)) => source > dest,
+                    _ => true,
+                };
+                if is_newer {
+                    Ok((Some(to.to_path_buf()), ConflictOutcome::Overwritten))
+                } else {
+                    Ok((None, ConflictOutcome::Skipped))
+                }
+            }
+        }
+    }
+}
+
+/// First free path of `name (1).ext`, `name (2).ext`, ...
+pub fn free_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().to_string_ex();
+    let (stem, ext) = split_name(&file_name);
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut count = 1;
+    loop {
+        let name = match ext.is_empty() {
+            true => format!("{} ({})", stem, count),
+            false => format!("{} ({}).{}", stem, count, ext),
+        };
+        let candidate = dir.join(name);
+        if fs::symlink_metadata(&candidate).is_err() {
+            return candidate;
+        }
+        count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_conflict_policy() {
+        let test_dir = "test_conflict_policy";
+        fs::create_dir_all(test_dir).unwrap();
+        let path = PathBuf::from(format!("{}/a.jpg", test_dir));
+        let free = PathBuf::from(format!("{}/free", test_dir));
+        fs::write(&path, b"a").unwrap();
+        fs::write(format!("{}/a (1).jpg", test_dir), b"a").unwrap();
+
+        let (to, outcome) = ConflictPolicy::Fail.resolve(&free, None).unwrap();
+        assert_eq!((to, outcome), (Some(free), ConflictOutcome::Done));
+        assert!(ConflictPolicy::Fail.resolve(&path, None).is_err());
+        assert_eq!(
+            ConflictPolicy::Skip.resolve(&path, None).unwrap(),
+            (None, ConflictOutcome::Skipped)
+        );
+        let keep_both = PathBuf::from(format!("{}/a (2).jpg", test_dir));
+        assert_eq!(
+            ConflictPolicy::KeepBoth.resolve(&path, None).unwrap(),
+            (
+                Some(keep_both.clone()),
+                ConflictOutcome::KeptBoth(keep_both)
+            )
+        );
+
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1);
+        assert_eq!(
+            ConflictPolicy::OverwriteIfNewer
+                .resolve(&path, Some(old))
+                .unwrap()
+                .1,
+            ConflictOutcome::Skipped
+        );
+        assert_eq!(
+            ConflictPolicy::OverwriteIfNewer
+                .resolve(&path, Some(SystemTime::now() + Duration::from_secs(60)))
+                .unwrap()
+                .1,
+            ConflictOutcome::Overwritten
+        );
+
+        assert_eq!(
+            free_path(Path::new("dir/noext")),
+            PathBuf::from("dir/noext (1)")
+        );
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+use crate::file::{walk_with, zip_util::ZipUtil, FileInfo, WalkOptions};
+
+// 部分ハッシュで読む先頭のバイト数
+const PARTIAL_HASH_LEN: u64 = 16 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    /// Content hash (blake3, hex)
+    pub hash: String,
+    pub infos: Vec<FileInfo>,
+}
+
+impl DuplicateGroup {
+    /// Bytes that could be freed by keeping only one copy
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size * (self.infos.len() as u64 - 1)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DuplicateReport {
+    /// Sorted by wasted bytes (largest first)
+    pub groups: Vec<DuplicateGroup>,
+    pub wasted_bytes: u64,
+    /// Files and zips which could not be read (skipped)
+    #[serde(default)]
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+/// Find files with the same contents. `infos` can mix files and zip entries (`ZipUtil::read_file_infos`).
+/// Candidates are narrowed by size, then the hash of the head, then the hash of the whole contents.
+/// Dirs and empty files are ignored. Unreadable files are skipped and returned in `failures`.
+pub fn find_duplicates(infos: Vec<FileInfo>) -> Result<DuplicateReport> {
+    let mut failures: Vec<(PathBuf, String)> = Vec::new();
+
+    // size =====
+    let mut by_size: BTreeMap<u64, Vec<FileInfo>> = BTreeMap::new();
+    for mut info in infos.into_iter().filter(|info| info.is_file) {
+        let size = content_size(&mut info);
+        if size > 0 {
+            by_size.entry(size).or_default().push(info);
+        }
+    }
+
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    for (size, infos) in by_size.into_iter().filter(|(_, infos)| infos.len() > 1) {
+        // partial hash =====
+        let partial = group_by_hash(infos, Some(PARTIAL_HASH_LEN), &mut failures);
+
+        for (hash, infos) in partial {
+            // 先頭だけで全体を読んでいるなら、そのまま確定
+            let full = if size <= PARTIAL_HASH_LEN {
+                vec![(hash, infos)]
+            } else {
+                group_by_hash(infos, None, &mut failures)
+            };
+
+            groups.extend(full.into_iter().map(|(hash, infos)| DuplicateGroup {
+                size,
+                hash: blake3::Hash::from(hash).to_hex().to_string(),
+                infos,
+            }));
+        }
+    }
+
+    groups.sort_by_key(|group| std::cmp::Reverse(group.wasted_bytes()));
+    let wasted_bytes = groups.iter().map(|group| group.wasted_bytes()).sum();
+    Ok(DuplicateReport {
+        groups,
+        wasted_bytes,
+        failures,
+    })
+}
+
+/// Walk `dirs` and find duplicates among the files. With `include_zips`, entries of zip files are compared too.
+/// Broken zips are reported in `failures`.
+pub fn find_duplicates_in(
+    dirs: &[&str],
+    options: WalkOptions,
+    include_zips: bool,
+) -> Result<DuplicateReport> {
+    let mut infos: Vec<FileInfo> = Vec::new();
+    let mut zip_failures: Vec<(PathBuf, String)> = Vec::new();
+    for dir in dirs {
+        for entry in walk_with(dir, options.clone()) {
+            let info = entry?.info;
+            if include_zips && info.is_zip {
+                // エントリ一覧だけ取って、アーカイブはすぐ閉じる
+                match ZipUtil::read_file_infos(&info.path_string()) {
+                    Ok((_, zip_infos)) => infos.extend(zip_infos),
+                    Err(e) => zip_failures.push((info.path.clone(), format!("{:#}", e))),
+                }
+            }
+            infos.push(info);
+        }
+    }
+    let mut report = find_duplicates(infos)?;
+    report.failures.splice(0..0, zip_failures);
+    Ok(report)
+}
+
+/// Hash (blake3, hex) of the whole contents of a file
+pub fn file_hash(path: &Path) -> Result<String> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open. Path: {}", path.display()))?;
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut BufReader::new(file), &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+fn content_size(info: &mut FileInfo) -> u64 {
+    if let Some(zip_info) = &info.zip_info {
+        return zip_info.size;
+    }
+    if info.meta.is_none() {
+        info.load_meta();
+    }
+    info.meta.as_ref().map_or(0, |meta| meta.size)
+}
+
+// 1件しかないハッシュは重複ではないので除く
+// zip のエントリはアーカイブごとにまとめて、開いたアーカイブは読み終わったら閉じる
+fn group_by_hash(
+    infos: Vec<FileInfo>,
+    limit: Option<u64>,
+    failures: &mut Vec<(PathBuf, String)>,
+) -> Vec<([u8; 32], Vec<FileInfo>)> {
+    let mut by_hash: BTreeMap<[u8; 32], Vec<FileInfo>> = BTreeMap::new();
+    let mut by_archive: BTreeMap<String, Vec<FileInfo>> = BTreeMap::new();
+    for info in infos {
+        match &info.zip_info {
+            Some(zip_info) => by_archive
+                .entry(zip_info.zip_path.clone())
+                .or_default()
+                .push(info),
+            None => match hash_file(&info, limit) {
+                Ok(hash) => by_hash.entry(*hash.as_bytes()).or_default().push(info),
+                Err(e) => failures.push((info.path.clone(), format!("{:#}", e))),
+            },
+        }
+    }
+
+    for (zip_path, infos) in by_archive {
+        let mut archive = match ZipUtil::open(&zip_path) {
+            Ok(archive) => archive,
+            Err(e) => {
+                let message = format!("Failed to open zip. Path: {}, {:#}", zip_path, e);
+                failures.extend(infos.into_iter().map(|info| (info.path, message.clone())));
+                continue;
+            }
+        };
+        for info in infos {
+            match hash_zip_entry(&mut archive, &info, limit) {
+                Ok(hash) => by_hash.entry(*hash.as_bytes()).or_default().push(info),
+                Err(e) => failures.push((info.path.clone(), format!("{:#}", e))),
+            }
+        }
+    }
+
+    by_hash
+        .into_iter()
+        .filter(|(_, infos)| infos.len() > 1)
+        .collect()
+}
+
+fn hash_file(info: &FileInfo, limit: Option<u64>) -> Result<blake3::Hash> {
+    let file = File::open(&info.path)
+        .with_context(|| format!("Failed to open. Path: {}", info.path_string()))?;
+    let mut hasher = blake3::Hasher::new();
+    io::copy(
+        &mut BufReader::new(file).take(limit.unwrap_or(u64::MAX)),
+        &mut hasher,
+    )?;
+    Ok(hasher.finalize())
+}
+
+fn hash_zip_entry(
+    archive: &mut ZipArchive<BufReader<File>>,
+    info: &FileInfo,
+    limit: Option<u64>,
+) -> Result<blake3::Hash> {
+    let name = info
+        .zip_info
+        .as_ref()
+        .map_or("", |zip_info| zip_info.name.as_str());
+    let entry = archive.by_name(name)?;
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut entry.take(limit.unwrap_or(u64::MAX)), &mut hasher)?;
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_find_duplicates() {
+        let test_dir = "test_find_duplicates";
+        std::fs::create_dir_all(format!("{}/sub", test_dir)).unwrap();
+        let same = vec![7u8; PARTIAL_HASH_LEN as usize + 10];
+        let mut differ_tail = same.clone();
+        *differ_tail.last_mut().unwrap() = 8;
+        std::fs::write(format!("{}/a.jpg", test_dir), &same).unwrap();
+        std::fs::write(format!("{}/sub/b.jpg", test_dir), &same).unwrap();
+        std::fs::write(format!("{}/c.jpg", test_dir), &differ_tail).unwrap();
+        std::fs::write(format!("{}/small1.txt", test_dir), b"abc").unwrap();
+        std::fs::write(format!("{}/small2.txt", test_dir), b"abd").unwrap();
+        std::fs::write(format!("{}/empty1.txt", test_dir), b"").unwrap();
+        std::fs::write(format!("{}/empty2.txt", test_dir), b"").unwrap();
+
+        // zip entry with the same contents
+        {
+            let file = File::create(format!("{}/archive.zip", test_dir)).unwrap();
+            let mut writer = zip::ZipWriter::new(file);
+            writer
+                .start_file("in_zip.jpg", zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(&same).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let report = find_duplicates_in(&[test_dir], WalkOptions::default(), false).unwrap();
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].infos.len(), 2);
+        assert_eq!(report.wasted_bytes, same.len() as u64);
+
+        let report = find_duplicates_in(&[test_dir], WalkOptions::default(), true).unwrap();
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].infos.len(), 3);
+        assert!(report.groups[0].infos.iter().any(|info| info.in_zip()));
+        assert_eq!(report.wasted_bytes, same.len() as u64 * 2);
+        assert!(report.failures.is_empty());
+
+        // 途中で消えたファイルは飛ばして報告する
+        std::fs::write(format!("{}/gone.jpg", test_dir), &same).unwrap();
+        let mut infos = crate::file::read_dir_deep(test_dir, 10).unwrap();
+        for info in infos.iter_mut() {
+            info.load_meta();
+        }
+        std::fs::remove_file(format!("{}/gone.jpg", test_dir)).unwrap();
+        let report = find_duplicates(infos).unwrap();
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].infos.len(), 2);
+        assert_eq!(report.failures.len(), 1);
+        assert!(report.failures[0].0.ends_with("gone.jpg"));
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+
+use once_cell::sync::Lazy;
+
+use crate::file::FileKind;
+use crate::rwlock::A2RwOptionLock;
+
+static DEFAULT_KINDS: &[(FileKind, &[&str])] = &[
+    // image/movie are the same as is_image/is_movie of old versions. Register others (heic, mkv, ...) if needed.
+    (FileKind::Image, &["jpeg", "jpg", "gif", "webp", "png"]),
+    (
+        FileKind::Movie,
+        &["mp4", "mpeg", "mpg", "avi", "mov", "webm"],
+    ),
+    (
+        FileKind::Audio,
+        &["mp3", "m4a", "aac", "flac", "wav", "ogg", "opus", "wma"],
+    ),
+    (
+        FileKind::Document,
+        &[
+            "pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt", "ods", "odp", "epub",
+        ],
+    ),
+    (
+        FileKind::Text,
+        &[
+            "txt", "md", "csv", "json", "xml", "yaml", "yml", "toml", "log", "ini", "html", "htm",
+        ],
+    ),
+    (
+        FileKind::Archive,
+        &["zip", "cbz", "rar", "cbr", "7z", "tar", "gz", "bz2", "xz"],
+    ),
+    (FileKind::Subtitle, &["srt", "ass", "ssa", "vtt", "sub"]),
+];
+static DEFAULT_ZIP_EXTENSIONS: &[&str] = &["zip"];
+
+static REGISTRY: Lazy<A2RwOptionLock<FileKindRegistry>> =
+    Lazy::new(|| A2RwOptionLock::new(Some(FileKindRegistry::default())));
+
+/// Extension (lowercase, without ".") to `FileKind` table.
+/// `FileInfo` resolves kinds from the global registry, so extend it at startup.
+///
+/// `FileKindRegistry::configure(|registry| registry.register(FileKind::Image, &["jxl"]))`
+#[derive(Debug, Clone)]
+pub struct FileKindRegistry {
+    kinds: HashMap<String, FileKind>,
+    // extensions readable by ZipUtil (is_zip)
+    zip_extensions: HashSet<String>,
+}
+
+impl Default for FileKindRegistry {
+    fn default() -> Self {
+        let mut registry = FileKindRegistry::empty();
+        for (kind, extensions) in DEFAULT_KINDS {
+            registry.register(*kind, extensions);
+        }
+        registry.register_zip(DEFAULT_ZIP_EXTENSIONS);
+        registry
+    }
+}
+
+impl FileKindRegistry {
+    pub fn empty() -> Self {
+        FileKindRegistry {
+            kinds: HashMap::new(),
+            zip_extensions: HashSet::new(),
+        }
+    }
+
+    /// Register extensions as the kind. Already registered extensions are overwritten.
+    pub fn register(&mut self, kind: FileKind, extensions: &[&str]) {
+        for ext in extensions {
+            self.kinds.insert(ext.to_lowercase(), kind);
+        }
+    }
+
+    pub fn unregister(&mut self, extensions: &[&str]) {
+        for ext in extensions {
+            let ext = ext.to_lowercase();
+            self.kinds.remove(&ext);
+            self.zip_extensions.remove(&ext);
+        }
+    }
+
+    /// Register extensions of zip format (e.g. "cbz"). They are also registered as `FileKind::Archive`.
+    pub fn register_zip(&mut self, extensions: &[&str]) {
+        self.register(FileKind::Archive, extensions);
+        for ext in extensions {
+            self.zip_extensions.insert(ext.to_lowercase());
+        }
+    }
+
+    pub fn kind_of(&self, extension: &str) -> FileKind {
+        self.kinds
+            .get(extension)
+            .copied()
+            .unwrap_or(FileKind::Other)
+    }
+
+    pub fn is_zip(&self, extension: &str) -> bool {
+        self.zip_extensions.contains(extension)
+    }
+
+    pub fn extensions_of(&self, kind: FileKind) -> Vec<String> {
+        let mut extensions: Vec<String> = self
+            .kinds
+            .iter()
+            .filter(|(_, k)| **k == kind)
+            .map(|(ext, _)| ext.clone())
+            .collect();
+        extensions.sort();
+        extensions
+    }
+
+    /// Read the global registry
+    pub fn with<R: Default>(f: impl FnOnce(&FileKindRegistry) -> R) -> R {
+        REGISTRY.with(f).unwrap_or_default()
+    }
+
+    /// Update the global registry
+    pub fn configure(f: impl FnOnce(&mut FileKindRegistry)) {
+        let _ = REGISTRY.with_mut(f);
+    }
+
+    /// Replace the global registry
+    pub fn set_global(registry: FileKindRegistry) {
+        let _ = REGISTRY.set(registry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_kind_registry() {
+        let mut registry = FileKindRegistry::default();
+        assert_eq!(registry.kind_of("jpg"), FileKind::Image);
+        assert_eq!(registry.kind_of("srt"), FileKind::Subtitle);
+        assert_eq!(registry.kind_of("unknown"), FileKind::Other);
+        assert!(registry.is_zip("zip"));
+        assert!(!registry.is_zip("cbz"));
+
+        registry.register(FileKind::Image, &["JXL"]);
+        registry.register_zip(&["cbz"]);
+        registry.unregister(&["gif"]);
+        assert_eq!(registry.kind_of("jxl"), FileKind::Image);
+        assert!(registry.is_zip("cbz"));
+        assert_eq!(registry.kind_of("gif"), FileKind::Other);
+        assert!(registry
+            .extensions_of(FileKind::Image)
+            .contains(&"jxl".to_string()));
+
+        // defaults of image/movie are not widened
+        assert_eq!(
+            FileKindRegistry::default().extensions_of(FileKind::Image),
+            ["gif", "jpeg", "jpg", "png", "webp"]
+        );
+        assert_eq!(registry.kind_of("heic"), FileKind::Other);
+        assert_eq!(registry.kind_of("mkv"), FileKind::Other);
+    }
+}
@@ -0,0 +1,192 @@
+// 簡易 glob マッチ
+// `*`: '/' 以外の0文字以上, `**`: '/' を含む0文字以上, `?`: '/' 以外の1文字, `[a-z]` `[!a]`: 文字クラス, `\`: エスケープ
+// パターンの位置の集合を1文字ずつ進める (NFA)。戻りがないので O(pattern * text)
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let tokens = parse(pattern);
+    let mut states = vec![false; tokens.len() + 1];
+    states[0] = true;
+    close(&tokens, &mut states);
+
+    for c in text.chars() {
+        let mut next = vec![false; tokens.len() + 1];
+        for (i, token) in tokens.iter().enumerate() {
+            if !states[i] {
+                continue;
+            }
+            match token {
+                Token::Star => next[i] |= c != '/',
+                Token::GlobStar => next[i] = true,
+                Token::SkipDirs => {}
+                token => next[i + 1] |= token.matches(c),
+            }
+        }
+        if !next.contains(&true) {
+            return false;
+        }
+        close(&tokens, &mut next);
+        states = next;
+    }
+    states[tokens.len()]
+}
+
+// `*` / `**` は0文字でもよく、"**/" は丸ごと飛ばせる (前から順に伝える)
+fn close(tokens: &[Token], states: &mut [bool]) {
+    for (i, token) in tokens.iter().enumerate() {
+        if !states[i] {
+            continue;
+        }
+        match token {
+            Token::Star | Token::GlobStar => states[i + 1] = true,
+            Token::SkipDirs => {
+                states[i + 1] = true;
+                states[i + 3] = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    Class(CharClass),
+    /// `*`
+    Star,
+    /// `**`
+    GlobStar,
+    /// Put before `**` `/` of "**/", which also matches zero directories
+    SkipDirs,
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(ch) => *ch == c,
+            Token::Any => c != '/',
+            Token::Class(class) => c != '/' && class.matches(c),
+            Token::Star | Token::GlobStar | Token::SkipDirs => false,
+        }
+    }
+}
+
+fn parse(pattern: &str) -> Vec<Token> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            '*' if pattern.get(i + 1) == Some(&'*') => {
+                if pattern.get(i + 2) == Some(&'/') {
+                    tokens.extend([Token::SkipDirs, Token::GlobStar, Token::Char('/')]);
+                    i += 3;
+                } else {
+                    tokens.push(Token::GlobStar);
+                    i += 2;
+                }
+            }
+            '*' => {
+                tokens.push(Token::Star);
+                i += 1;
+            }
+            '?' => {
+                tokens.push(Token::Any);
+                i += 1;
+            }
+            '[' => match parse_class(&pattern[i..]) {
+                Some((class, len)) => {
+                    tokens.push(Token::Class(class));
+                    i += len;
+                }
+                // not closed: literal '['
+                None => {
+                    tokens.push(Token::Char('['));
+                    i += 1;
+                }
+            },
+            '\\' if i + 1 < pattern.len() => {
+                tokens.push(Token::Char(pattern[i + 1]));
+                i += 2;
+            }
+            c => {
+                tokens.push(Token::Char(c));
+                i += 1;
+            }
+        }
+    }
+    tokens
+}
+
+struct CharClass {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        let found = self.ranges.iter().any(|(from, to)| *from <= c && c <= *to);
+        found != self.negated
+    }
+}
+
+// parse "[...]" at the head of pattern. returns class and consumed length
+fn parse_class(pattern: &[char]) -> Option<(CharClass, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let start = i;
+    loop {
+        let c = *pattern.get(i)?;
+        // ']' right after '[' is a literal
+        if c == ']' && i > start {
+            return Some((CharClass { negated, ranges }, i + 1));
+        }
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|c| *c != ']') {
+            ranges.push((c, pattern[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.jpg", "image.jpg"));
+        assert!(!glob_match("*.jpg", "dir/image.jpg"));
+        assert!(glob_match("**/*.jpg", "image.jpg"));
+        assert!(glob_match("**/*.jpg", "a/b/image.jpg"));
+        assert!(glob_match("a/**/c", "a/c"));
+        assert!(glob_match("a/**/c", "a/b/b/c"));
+        assert!(glob_match("page_??.png", "page_01.png"));
+        assert!(glob_match("[0-9]*", "1.txt"));
+        assert!(!glob_match("[!0-9]*", "1.txt"));
+        assert!(glob_match("\\*", "*"));
+        assert!(glob_match("画像*", "画像01.jpg"));
+        assert!(glob_match("a/**", "a/b/c"));
+        assert!(glob_match("**c", "a/b/c"));
+        assert!(glob_match("*/**/*.jpg", "a/b/c/d.jpg"));
+        assert!(!glob_match("a*b", "a/b"));
+        assert!(glob_match("[", "["));
+
+        // 戻りが指数的にならない
+        let long = "a".repeat(200);
+        assert!(!glob_match("*a*a*a*a*a*a*a*b", &long));
+        assert!(!glob_match("**a**a**a**a**a**b", &long));
+
+        // `**` inside a segment
+        assert!(glob_match("*?**/?", "baa"));
+        assert!(glob_match("**b**/a", "bbba"));
+        assert!(glob_match("*a**/b**", "aaaba"));
+        assert!(glob_match("?**/a**/b", "aaa/ab"));
+    }
+}
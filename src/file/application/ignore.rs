@@ -0,0 +1,251 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::file::application::glob::glob_match;
+
+/// Patterns for NAS / OS / tool junk. Use them as `IgnoreOptions.global`.
+pub const JUNK_PATTERNS: &[&str] = &[
+    ".git/",
+    "node_modules/",
+    "@eaDir/",
+    "#recycle/",
+    "Thumbs.db",
+    "desktop.ini",
+    ".DS_Store",
+];
+
+#[derive(Debug, Clone)]
+pub struct IgnoreOptions {
+    /// Ignore files read in each directory (gitignore syntax)
+    pub files: Vec<String>,
+    /// Patterns applied to the whole walk (gitignore syntax, relative to the walk root).
+    /// Ignore files in the tree take priority over them.
+    pub global: Vec<String>,
+}
+
+impl Default for IgnoreOptions {
+    fn default() -> Self {
+        IgnoreOptions {
+            files: vec![".gitignore".to_string(), ".ignore".to_string()],
+            global: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    glob: String,
+    negated: bool,
+    dir_only: bool,
+    // パターンに / を含む場合は base からの相対パスで、含まない場合はファイル名で比較する
+    anchored: bool,
+}
+
+impl IgnorePattern {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        // trailing spaces are ignored unless escaped
+        let line = if line.ends_with("\\ ") {
+            line
+        } else {
+            line.trim_end_matches(' ')
+        };
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let line = match line.strip_prefix('\\') {
+            Some(rest) if rest.starts_with('#') || rest.starts_with('!') => rest,
+            _ => line,
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        if line.is_empty() {
+            return None;
+        }
+
+        let anchored = line.contains('/');
+        let glob = line.trim_start_matches('/').to_string();
+        Some(IgnorePattern {
+            glob,
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, relative: &str, file_name: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            glob_match(&self.glob, relative)
+        } else {
+            glob_match(&self.glob, file_name)
+        }
+    }
+}
+
+/// Patterns of one ignore file
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    patterns: Vec<IgnorePattern>,
+}
+
+impl IgnoreRules {
+    pub fn parse(text: &str) -> Self {
+        IgnoreRules {
+            patterns: text.lines().filter_map(IgnorePattern::parse).collect(),
+        }
+    }
+
+    pub fn from_patterns<S: AsRef<str>>(patterns: &[S]) -> Self {
+        IgnoreRules {
+            patterns: patterns
+                .iter()
+                .filter_map(|pattern| IgnorePattern::parse(pattern.as_ref()))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Some(true): ignored, Some(false): re-included by "!", None: no pattern matched
+    pub fn check(&self, relative: &str, is_dir: bool) -> Option<bool> {
+        let file_name = relative.rsplit('/').next().unwrap_or(relative);
+        // 後に書かれたパターンが優先
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(relative, file_name, is_dir))
+            .map(|pattern| !pattern.negated)
+    }
+}
+
+/// Ignore rules of a directory and its ancestors
+#[derive(Debug)]
+pub(crate) struct IgnoreLevel {
+    base: PathBuf,
+    rules: IgnoreRules,
+    parent: Option<Arc<IgnoreLevel>>,
+}
+
+impl IgnoreLevel {
+    pub(crate) fn root(base: &Path, options: &IgnoreOptions) -> Option<Arc<IgnoreLevel>> {
+        let rules = IgnoreRules::from_patterns(&options.global);
+        if rules.is_empty() {
+            return None;
+        }
+        Some(Arc::new(IgnoreLevel {
+            base: base.to_path_buf(),
+            rules,
+            parent: None,
+        }))
+    }
+
+    /// Read ignore files in `dir` and stack them on `parent`
+    pub(crate) fn load(
+        dir: &Path,
+        options: &IgnoreOptions,
+        parent: Option<Arc<IgnoreLevel>>,
+    ) -> Option<Arc<IgnoreLevel>> {
+        let text: String = options
+            .files
+            .iter()
+            .filter_map(|name| std::fs::read_to_string(dir.join(name)).ok())
+            .collect::<Vec<String>>()
+            .join("\n");
+        let rules = IgnoreRules::parse(&text);
+        if rules.is_empty() {
+            return parent;
+        }
+        Some(Arc::new(IgnoreLevel {
+            base: dir.to_path_buf(),
+            rules,
+            parent,
+        }))
+    }
+
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut level = Some(self);
+        while let Some(current) = level {
+            if let Ok(relative) = path.strip_prefix(&current.base) {
+                if let Some(ignored) = current.rules.check(&slash_path(relative), is_dir) {
+                    return ignored;
+                }
+            }
+            level = current.parent.as_deref();
+        }
+        false
+    }
+}
+
+// パターンは '/' 区切り (Windows の '\' ではマッチしない)
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::{walk_with, WalkOptions};
+
+    #[test]
+    fn test_ignore_rules() {
+        let rules = IgnoreRules::parse("# comment\n*.log\n!keep.log\n/build\ncache/\ndoc/*.txt\n");
+        assert_eq!(rules.check("a.log", false), Some(true));
+        assert_eq!(rules.check("sub/a.log", false), Some(true));
+        assert_eq!(rules.check("keep.log", false), Some(false));
+        assert_eq!(rules.check("build", true), Some(true));
+        assert_eq!(rules.check("sub/build", true), None);
+        assert_eq!(rules.check("sub/cache", true), Some(true));
+        assert_eq!(rules.check("cache", false), None);
+        assert_eq!(rules.check("doc/a.txt", false), Some(true));
+        assert_eq!(rules.check("doc/sub/a.txt", false), None);
+
+        // OS の区切り文字によらず '/' で照合する
+        let path = Path::new("doc").join("a.txt");
+        assert_eq!(slash_path(&path), "doc/a.txt");
+        assert_eq!(rules.check(&slash_path(&path), false), Some(true));
+    }
+
+    #[test]
+    fn test_walk_with_ignore() {
+        let test_dir = "test_walk_with_ignore";
+        std::fs::create_dir_all(format!("{}/@eaDir", test_dir)).unwrap();
+        std::fs::create_dir_all(format!("{}/sub/tmp", test_dir)).unwrap();
+        std::fs::write(format!("{}/@eaDir/a.jpg", test_dir), b"test").unwrap();
+        std::fs::write(format!("{}/.gitignore", test_dir), b"*.tmp\n").unwrap();
+        std::fs::write(format!("{}/sub/.ignore", test_dir), b"!b.tmp\n/tmp/\n").unwrap();
+        std::fs::write(format!("{}/sub/a.tmp", test_dir), b"test").unwrap();
+        std::fs::write(format!("{}/sub/b.tmp", test_dir), b"test").unwrap();
+        std::fs::write(format!("{}/sub/tmp/c.jpg", test_dir), b"test").unwrap();
+        std::fs::write(format!("{}/Thumbs.db", test_dir), b"test").unwrap();
+
+        let options = WalkOptions {
+            ignore: Some(IgnoreOptions {
+                global: JUNK_PATTERNS.iter().map(|p| p.to_string()).collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut names: Vec<String> = walk_with(test_dir, options)
+            .map(|entry| entry.unwrap().info.file_name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec![".gitignore", ".ignore", "b.tmp", "sub"]);
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+}
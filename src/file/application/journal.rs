@@ -0,0 +1,503 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::file::{
+    move_file_with, write_with, ConflictPolicy, MoveOptions, PathUtil, RenameItem, RenamePlan,
+    Trash, TrashItem, WriteOptions,
+};
+use crate::time::Timestamp;
+
+// 保持する履歴の上限
+const JOURNAL_LIMIT: usize = 100;
+
+/// Mutating file operation with enough information to invert it
+#[allow(clippy::large_enum_variant)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum FileOp {
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+        /// Parent dirs of `to` created by the move, outermost first (removed by undo if empty)
+        #[serde(default)]
+        created_dirs: Vec<PathBuf>,
+    },
+    Trash {
+        trash_dir: PathBuf,
+        item: TrashItem,
+    },
+    /// Created dirs, outermost first
+    CreateDir {
+        paths: Vec<PathBuf>,
+    },
+    BatchRename {
+        items: Vec<RenameItem>,
+    },
+}
+
+impl FileOp {
+    // 操作後に存在するはずの path
+    fn results(&self) -> Vec<PathBuf> {
+        match self {
+            FileOp::Move { to, .. } => vec![to.clone()],
+            FileOp::Trash { trash_dir, item } => {
+                vec![trash_dir.join("files").join(&item.name)]
+            }
+            FileOp::CreateDir { paths } => paths.clone(),
+            FileOp::BatchRename { items } => changed(items).map(|i| i.to.clone()).collect(),
+        }
+    }
+
+    // 元に戻した後に存在するはずの path
+    fn sources(&self) -> Vec<PathBuf> {
+        match self {
+            FileOp::Move { from, .. } => vec![from.clone()],
+            FileOp::Trash { item, .. } => vec![item.original_path.clone()],
+            FileOp::CreateDir { .. } => Vec::new(),
+            FileOp::BatchRename { items } => changed(items).map(|i| i.from.clone()).collect(),
+        }
+    }
+}
+
+fn changed(items: &[RenameItem]) -> impl Iterator<Item = &RenameItem> {
+    items.iter().filter(|item| item.is_changed())
+}
+
+/// State of a path to detect changes made outside of the journal
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: u64, // nanos
+}
+
+impl Fingerprint {
+    fn read(path: &Path) -> Option<Fingerprint> {
+        let meta = fs::symlink_metadata(path).ok()?;
+        // ディレクトリの更新日時は中身の出し入れ (undo した子の削除など) で変わるので見ない
+        let modified = match meta.is_dir() {
+            true => 0,
+            false => meta
+                .modified()
+                .ok()
+                .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_nanos() as u64),
+        };
+        Some(Fingerprint {
+            path: path.to_path_buf(),
+            is_dir: meta.is_dir(),
+            size: if meta.is_dir() { 0 } else { meta.len() },
+            modified,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournalEntry {
+    pub op: FileOp,
+    pub at: u64, // Timestamp
+    /// State just after the op (or just after the undo for redo entries)
+    pub state: Vec<Fingerprint>,
+}
+
+/// Undo/redo history of file operations. Operations done through the journal are recorded,
+/// and undo/redo abort when the files were changed since.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Journal {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    undo: Vec<JournalEntry>,
+    redo: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// In-memory journal
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Journal persisted as JSON at `path` (loaded if exists)
+    pub fn open(path: &str) -> Result<Self> {
+        let mut journal = match fs::read(path) {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Journal::default(),
+            Err(e) => return Err(e.into()),
+        };
+        journal.path = Some(PathBuf::from(path));
+        Ok(journal)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => {
+                let json = serde_json::to_vec_pretty(self)?;
+                write_with(path.clone(), &json, &WriteOptions::atomic())?;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Recorded entries (oldest first)
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.undo
+    }
+
+    /// `move_file` which fails if `to` exists (an overwritten file could not be restored by undo)
+    pub fn move_file(&mut self, from: &str, to: &str) -> Result<()> {
+        let created_dirs = move_no_overwrite(Path::new(from), Path::new(to))?;
+        self.record(FileOp::Move {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+            created_dirs,
+        })
+    }
+
+    /// `rename` which fails if `to` exists
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        move_file_with(from, to, &no_overwrite(), |_| {})?;
+        self.record(FileOp::Move {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+            created_dirs: Vec::new(),
+        })
+    }
+
+    pub fn trash(&mut self, trash: &Trash, path: &str) -> Result<TrashItem> {
+        let item = trash.trash(path)?;
+        self.record(FileOp::Trash {
+            trash_dir: trash.dir.clone(),
+            item: item.clone(),
+        })?;
+        Ok(item)
+    }
+
+    /// Create a dir (and missing parents, each recorded to be removed by undo)
+    pub fn create_dir(&mut self, path: &str) -> Result<()> {
+        let missing = missing_dirs(Path::new(path));
+        fs::create_dir_all(path)?;
+        if missing.is_empty() {
+            return Ok(());
+        }
+        self.record(FileOp::CreateDir { paths: missing })
+    }
+
+    /// Plans which overwrite existing files (`RenamePlan::resolve` with `Overwrite`) are rejected,
+    /// because undo could not bring the overwritten files back.
+    pub fn batch_rename(&mut self, plan: &RenamePlan) -> Result<()> {
+        if let Some(path) = plan.overwrite.first() {
+            return Err(anyhow!(
+                "Overwriting renames can not be undone. Path: {}",
+                path.to_string_ex()
+            ));
+        }
+        plan.apply()?;
+        self.record(FileOp::BatchRename {
+            items: plan.items.clone(),
+        })
+    }
+
+    /// Record an op which is already done
+    pub fn record(&mut self, op: FileOp) -> Result<()> {
+        let state = fingerprints(&op.results());
+        self.undo.push(JournalEntry {
+            op,
+            at: Timestamp::from_system_time(SystemTime::now()),
+            state,
+        });
+        if self.undo.len() > JOURNAL_LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+        self.save()
+    }
+
+    /// Invert the last op. None if nothing to undo.
+    pub fn undo(&mut self) -> Result<Option<FileOp>> {
+        let Some(entry) = self.undo.last() else {
+            return Ok(None);
+        };
+        check_state(&entry.state)?;
+        let mut entry = entry.clone();
+        invert(&entry.op)?;
+        self.undo.pop();
+
+        entry.state = fingerprints(&entry.op.sources());
+        let op = entry.op.clone();
+        self.redo.push(entry);
+        self.save()?;
+        Ok(Some(op))
+    }
+
+    /// Do the last undone op again. None if nothing to redo.
+    pub fn redo(&mut self) -> Result<Option<FileOp>> {
+        let Some(entry) = self.redo.last() else {
+            return Ok(None);
+        };
+        check_state(&entry.state)?;
+        let mut entry = entry.clone();
+        entry.op = replay(&entry.op)?;
+        self.redo.pop();
+
+        entry.state = fingerprints(&entry.op.results());
+        let op = entry.op.clone();
+        self.undo.push(entry);
+        self.save()?;
+        Ok(Some(op))
+    }
+}
+
+fn fingerprints(paths: &[PathBuf]) -> Vec<Fingerprint> {
+    paths
+        .iter()
+        .filter_map(|path| Fingerprint::read(path))
+        .collect()
+}
+
+fn check_state(state: &[Fingerprint]) -> Result<()> {
+    for expected in state {
+        if Fingerprint::read(&expected.path).as_ref() != Some(expected) {
+            return Err(anyhow!(
+                "Changed since the operation. Path: {}",
+                expected.path.to_string_ex()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn no_overwrite() -> MoveOptions {
+    MoveOptions {
+        conflict: ConflictPolicy::Fail,
+        ..Default::default()
+    }
+}
+
+// path と、存在しない親ディレクトリ (外側から順)
+fn missing_dirs(path: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = Vec::new();
+    let mut current = Some(path);
+    while let Some(dir) = current.filter(|d| !d.as_os_str().is_empty() && !d.exists()) {
+        missing.push(dir.to_path_buf());
+        current = dir.parent();
+    }
+    missing.reverse();
+    missing
+}
+
+// 空のものだけ深い方から消す (後から何か置かれたディレクトリは残す)
+fn remove_empty_dirs(paths: &[PathBuf]) {
+    for path in paths.iter().rev() {
+        let _ = fs::remove_dir(path);
+    }
+}
+
+// move_file と同じく親ディレクトリを作る (ただし上書きしない)。作ったディレクトリを返す
+fn move_no_overwrite(from: &Path, to: &Path) -> Result<Vec<PathBuf>> {
+    if fs::symlink_metadata(from).is_err() {
+        return Err(anyhow!(
+            "Source path does not exist. Path: {}",
+            from.to_string_ex()
+        ));
+    }
+    let created = match to.parent() {
+        Some(parent) => {
+            let missing = missing_dirs(parent);
+            fs::create_dir_all(parent)?;
+            missing
+        }
+        None => Vec::new(),
+    };
+    let result = move_file_with(
+        &from.to_string_ex(),
+        &to.to_string_ex(),
+        &no_overwrite(),
+        |_| {},
+    );
+    if let Err(e) = result {
+        remove_empty_dirs(&created);
+        return Err(e);
+    }
+    Ok(created)
+}
+
+fn invert(op: &FileOp) -> Result<()> {
+    match op {
+        FileOp::Move {
+            from,
+            to,
+            created_dirs,
+        } => {
+            move_no_overwrite(to, from)?;
+            remove_empty_dirs(created_dirs);
+            Ok(())
+        }
+        FileOp::Trash { trash_dir, item } => Trash::new(trash_dir).restore(item),
+        // 深い方から消す。空でなければ失敗する (中身は消さない)
+        FileOp::CreateDir { paths } => {
+            for path in paths.iter().rev() {
+                fs::remove_dir(path)?;
+            }
+            Ok(())
+        }
+        FileOp::BatchRename { items } => {
+            let items = items
+                .iter()
+                .map(|item| RenameItem {
+                    from: item.to.clone(),
+                    to: item.from.clone(),
+                })
+                .collect();
+            RenamePlan::from_items(items).apply()
+        }
+    }
+}
+
+// ゴミ箱の名前は変わることがあるので、実行後の op を返す
+fn replay(op: &FileOp) -> Result<FileOp> {
+    match op {
+        FileOp::Move { from, to, .. } => {
+            let created_dirs = move_no_overwrite(from, to)?;
+            return Ok(FileOp::Move {
+                from: from.clone(),
+                to: to.clone(),
+                created_dirs,
+            });
+        }
+        FileOp::Trash { trash_dir, item } => {
+            let item = Trash::new(trash_dir).trash(&item.original_path.to_string_ex())?;
+            return Ok(FileOp::Trash {
+                trash_dir: trash_dir.clone(),
+                item,
+            });
+        }
+        FileOp::CreateDir { paths } => {
+            for path in paths {
+                fs::create_dir(path)?;
+            }
+        }
+        FileOp::BatchRename { items } => RenamePlan::from_items(items.clone()).apply()?,
+    }
+    Ok(op.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal() {
+        let test_dir = "test_journal";
+        let journal_path = format!("{}.json", test_dir);
+        let a = format!("{}/a.txt", test_dir);
+        let b = format!("{}/moved/b.txt", test_dir);
+        fs::create_dir_all(test_dir).unwrap();
+        fs::write(&a, b"a").unwrap();
+
+        let mut journal = Journal::open(&journal_path).unwrap();
+        journal.move_file(&a, &b).unwrap();
+        assert!(Path::new(&b).exists());
+        let moved = format!("{}/moved", test_dir);
+        let FileOp::Move { created_dirs, .. } = &journal.entries()[0].op else {
+            panic!("not a move");
+        };
+        assert_eq!(created_dirs, &vec![PathBuf::from(&moved)]);
+
+        // never overwrite (undo could not restore it)
+        let other = format!("{}/other.txt", test_dir);
+        fs::write(&other, b"other").unwrap();
+        assert!(journal.move_file(&other, &b).is_err());
+        assert!(journal.rename(&other, &b).is_err());
+        assert_eq!(fs::read(&b).unwrap(), b"a");
+        fs::remove_file(&other).unwrap();
+
+        // persisted
+        let mut journal = Journal::open(&journal_path).unwrap();
+        assert_eq!(journal.entries().len(), 1);
+        journal.undo().unwrap().unwrap();
+        assert!(Path::new(&a).exists() && !Path::new(&b).exists());
+        // the created parent is removed too
+        assert!(!Path::new(&moved).exists());
+        journal.redo().unwrap().unwrap();
+        assert!(Path::new(&b).exists());
+        assert!(journal.redo().unwrap().is_none());
+
+        // changed since the op: abort
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        fs::write(&b, b"changed").unwrap();
+        assert!(journal.undo().is_err());
+        assert!(Path::new(&b).exists());
+        journal.undo.clear();
+
+        // trash and create_dir
+        let trash = Trash::new(format!("{}_trash", test_dir));
+        journal.trash(&trash, &b).unwrap();
+        assert!(!Path::new(&b).exists());
+        let created = format!("{}/new/deep", test_dir);
+        journal.create_dir(&created).unwrap();
+
+        assert_eq!(journal.entries().len(), 2);
+
+        // one undo removes all created dirs
+        journal.undo().unwrap();
+        assert!(!Path::new(&format!("{}/new", test_dir)).exists());
+        journal.redo().unwrap();
+        assert!(Path::new(&created).is_dir());
+        journal.undo().unwrap();
+        journal.undo().unwrap();
+        assert_eq!(fs::read(&b).unwrap(), b"changed");
+        assert!(trash.list().unwrap().is_empty());
+
+        journal.redo().unwrap();
+        assert!(!Path::new(&b).exists());
+        assert_eq!(trash.list().unwrap().len(), 1);
+
+        fs::remove_dir_all(test_dir).unwrap();
+        fs::remove_dir_all(&trash.dir).unwrap();
+        fs::remove_file(journal_path).unwrap();
+    }
+
+    #[test]
+    fn test_journal_batch_rename() {
+        let test_dir = "test_journal_batch_rename";
+        fs::create_dir_all(test_dir).unwrap();
+        fs::write(format!("{}/b.txt", test_dir), b"b").unwrap();
+        fs::write(format!("{}/c.txt", test_dir), b"c").unwrap();
+
+        let infos = crate::file::read_dir(test_dir).unwrap();
+        let plan = crate::file::BatchRename::new("{index}.{ext}")
+            .unwrap()
+            .plan(&infos);
+        let mut journal = Journal::new();
+        journal.batch_rename(&plan).unwrap();
+        assert_eq!(fs::read(format!("{}/1.txt", test_dir)).unwrap(), b"b");
+
+        journal.undo().unwrap();
+        assert_eq!(fs::read(format!("{}/b.txt", test_dir)).unwrap(), b"b");
+        assert_eq!(fs::read(format!("{}/c.txt", test_dir)).unwrap(), b"c");
+
+        // overwriting plans are rejected
+        let infos = vec![crate::file::FileInfo::from_str(&format!(
+            "{}/b.txt",
+            test_dir
+        ))];
+        let plan = crate::file::BatchRename::new("c.txt").unwrap().plan(&infos);
+        let (plan, _) = plan.resolve(ConflictPolicy::Overwrite).unwrap();
+        assert!(journal.batch_rename(&plan).is_err());
+        assert_eq!(fs::read(format!("{}/c.txt", test_dir)).unwrap(), b"c");
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+}
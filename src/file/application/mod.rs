@@ -1,8 +1,7 @@
 use crate::file::domain::file_info::FileInfo;
-use crate::file::FileMeta;
+use crate::file::{FileKind, FileMeta};
 use anyhow::{anyhow, Result};
-use std::fs::{self, File};
-use std::io::Write;
+use std::fs;
 use std::path::{Path, PathBuf};
 
 #[cfg(target_os = "windows")]
@@ -15,45 +14,166 @@ use windows::core::PCWSTR;
 use windows::Win32::Foundation::{FILETIME, INVALID_HANDLE_VALUE};
 #[cfg(target_os = "windows")]
 use windows::Win32::Storage::FileSystem::{
-    FindFirstFileW, FindNextFileW, FILE_ATTRIBUTE_DIRECTORY, WIN32_FIND_DATAW,
+    FindFirstFileW, FindNextFileW, FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_READONLY,
+    WIN32_FIND_DATAW,
 };
 
+pub mod atomic;
+pub mod batch_rename;
+pub mod cache;
+pub mod cancel;
+pub mod conflict;
+pub mod duplicates;
+pub mod file_kind_registry;
+pub(crate) mod glob;
+pub mod ignore;
+pub mod journal;
+pub mod parallel;
+pub mod query;
+pub mod search;
+pub mod snapshot;
+pub mod sniff;
+pub mod sort;
+pub mod stats;
+pub(crate) mod text;
+pub mod transfer;
+pub mod trash;
+pub mod tree;
+pub mod walk;
+pub mod watch;
 pub mod zip_util;
 
-static MOVIE_EXTENSIONS: &[&str] = &["mp4", "mpeg", "mpg", "avi", "mov", "webm"];
-static IMAGE_EXTENSIONS: &[&str] = &["jpeg", "jpg", "gif", "webp", "png"];
-static ZIP_EXTENSIONS: &[&str] = &["zip"];
+pub use atomic::*;
+pub use batch_rename::*;
+pub use cache::*;
+pub use cancel::*;
+pub use conflict::*;
+pub use duplicates::*;
+pub use file_kind_registry::*;
+pub use ignore::*;
+pub use journal::*;
+pub use parallel::*;
+pub use query::*;
+pub use search::*;
+pub use snapshot::*;
+pub use sniff::*;
+pub use sort::*;
+pub use stats::*;
+pub use transfer::*;
+pub use trash::*;
+pub use tree::*;
+pub use walk::*;
+pub use watch::*;
+
+#[cfg(target_os = "windows")]
 const WINDOWS_TO_UNIX_EPOCH: u64 = 116444736000000000;
 
 pub fn is_movie(extension: &str) -> bool {
-    MOVIE_EXTENSIONS.contains(&extension)
+    FileKindRegistry::with(|registry| registry.kind_of(extension) == FileKind::Movie)
 }
 
 pub fn is_image(extension: &str) -> bool {
-    IMAGE_EXTENSIONS.contains(&extension)
+    FileKindRegistry::with(|registry| registry.kind_of(extension) == FileKind::Image)
 }
 
 pub fn is_zip(extension: &str) -> bool {
-    ZIP_EXTENSIONS.contains(&extension)
+    FileKindRegistry::with(|registry| registry.is_zip(extension))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReadDirOptions {
+    /// Fill `FileInfo.meta` in the same pass (Windows: always filled).
+    /// On Unix it is a `statx`/`fstatat` relative to the directory, cheaper than `load_meta` per entry.
+    pub with_meta: bool,
+}
+
+/// Result of the lenient reads (`read_dir_lenient`, `read_dir_deep_lenient`)
+#[derive(Debug, Default)]
+pub struct ReadDirReport {
+    pub infos: Vec<FileInfo>,
+    /// Entries and directories which could not be read
+    pub failures: Vec<(PathBuf, anyhow::Error)>,
 }
 
 /// Read directory and return file infos.  
 /// It include all type (file, dirctory, symlink, etc...) infos
 #[cfg(not(target_os = "windows"))]
 pub fn read_dir(dir: &str) -> Result<Vec<FileInfo>> {
+    read_dir_with(dir, &ReadDirOptions::default())
+}
+
+/// `read_dir` with options
+#[cfg(not(target_os = "windows"))]
+pub fn read_dir_with(dir: &str, options: &ReadDirOptions) -> Result<Vec<FileInfo>> {
+    read_entries(dir, options, None)
+}
+
+/// `read_dir` which skips entries that can not be read and returns them in `failures`.
+/// It fails only when `dir` itself can not be read.
+#[cfg(not(target_os = "windows"))]
+pub fn read_dir_lenient(dir: &str, options: &ReadDirOptions) -> Result<ReadDirReport> {
+    let mut failures: Vec<(PathBuf, anyhow::Error)> = Vec::new();
+    let infos = read_entries(dir, options, Some(&mut failures))?;
+    Ok(ReadDirReport { infos, failures })
+}
+
+// failures が None なら最初のエラーで中断
+#[cfg(not(target_os = "windows"))]
+fn read_entries(
+    dir: &str,
+    options: &ReadDirOptions,
+    mut failures: Option<&mut Vec<(PathBuf, anyhow::Error)>>,
+) -> Result<Vec<FileInfo>> {
     let mut vec: Vec<FileInfo> = Vec::new();
 
     let path = Path::new(dir);
     let read_dir = fs::read_dir(path)?;
     for entry in read_dir {
-        let entry = entry?;
-        let file_info = FileInfo::from(entry);
-        vec.push(file_info);
+        let result = match entry {
+            Ok(entry) => read_entry(&entry, options).map_err(|e| (entry.path(), e)),
+            // 名前も取れない場合はディレクトリのパス
+            Err(e) => Err((path.to_path_buf(), e)),
+        };
+        match (result, failures.as_mut()) {
+            (Ok(file_info), _) => vec.push(file_info),
+            (Err((path, e)), Some(failures)) => failures.push((path, e.into())),
+            (Err((_, e)), None) => return Err(e.into()),
+        }
     }
 
     Ok(vec)
 }
 
+#[cfg(not(target_os = "windows"))]
+fn read_entry(entry: &fs::DirEntry, options: &ReadDirOptions) -> std::io::Result<FileInfo> {
+    let mut file_info = FileInfo::from_entry(entry)?;
+    if options.with_meta {
+        // DirEntry::metadata はディレクトリからの相対で stat する (symlink はたどらない)
+        // load_meta と同じく、symlink はリンク先の情報
+        let meta = match file_info.is_symlink {
+            true => fs::metadata(&file_info.path).or_else(|_| entry.metadata()),
+            false => entry.metadata(),
+        };
+        file_info.meta = meta.ok().map(|meta| FileMeta::from(&meta));
+    }
+    Ok(file_info)
+}
+
+/// `read_dir` with options (meta is always filled on Windows)
+#[cfg(target_os = "windows")]
+pub fn read_dir_with(dir: &str, _options: &ReadDirOptions) -> Result<Vec<FileInfo>> {
+    read_dir(dir)
+}
+
+/// `read_dir` which skips entries that can not be read (no per entry errors on Windows)
+#[cfg(target_os = "windows")]
+pub fn read_dir_lenient(dir: &str, _options: &ReadDirOptions) -> Result<ReadDirReport> {
+    Ok(ReadDirReport {
+        infos: read_dir(dir)?,
+        failures: Vec::new(),
+    })
+}
+
 #[cfg(target_os = "windows")]
 fn wide_cstr_to_osstring(buf: &[u16]) -> OsString {
     let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
@@ -71,6 +191,13 @@ fn filetime_to_unix_seconds(ft: FILETIME) -> u64 {
     (v - WINDOWS_TO_UNIX_EPOCH) / 10_000_000
 }
 
+// FILETIME は 100ns 単位
+#[cfg(target_os = "windows")]
+fn filetime_to_nsec(ft: FILETIME) -> u32 {
+    let v = ((ft.dwHighDateTime as u64) << 32) | (ft.dwLowDateTime as u64);
+    ((v % 10_000_000) * 100) as u32
+}
+
 #[cfg(target_os = "windows")]
 pub fn read_dir(dir: &str) -> Result<Vec<FileInfo>> {
     // windows codes
@@ -101,11 +228,17 @@ pub fn read_dir(dir: &str) -> Result<Vec<FileInfo>> {
                     modified: filetime_to_unix_seconds(data.ftLastWriteTime),
                     created: filetime_to_unix_seconds(data.ftCreationTime),
                     size: ((data.nFileSizeHigh as u64) << 32) | (data.nFileSizeLow as u64),
+                    accessed: filetime_to_unix_seconds(data.ftLastAccessTime),
+                    modified_nsec: filetime_to_nsec(data.ftLastWriteTime),
+                    created_nsec: filetime_to_nsec(data.ftCreationTime),
+                    accessed_nsec: filetime_to_nsec(data.ftLastAccessTime),
+                    readonly: data.dwFileAttributes & FILE_ATTRIBUTE_READONLY.0 != 0,
+                    ..Default::default()
                 };
 
                 let mut info = FileInfo::from_path(&full_path_buf);
-                info.is_dir = data.dwFileAttributes & FILE_ATTRIBUTE_DIRECTORY.0 != 0;
-                info.is_file = !info.is_dir;
+                let is_dir = data.dwFileAttributes & FILE_ATTRIBUTE_DIRECTORY.0 != 0;
+                info.set_type(is_dir, !is_dir);
                 info.meta = Some(meta);
 
                 vec.push(info);
@@ -121,27 +254,44 @@ pub fn read_dir(dir: &str) -> Result<Vec<FileInfo>> {
     }
 }
 
+/// Read `deep` levels of directories.
+/// Each listing comes whole, then the sub directories follow one by one (`WalkOrder::ListingFirst`).
+/// No ignore rules are applied (not even `.gitignore`). Use `read_dir_deep_with` with `WalkOptions.ignore`
+/// (e.g. `Some(IgnoreOptions::default())`) to skip ignored entries.
 pub fn read_dir_deep(dir: &str, deep: usize) -> Result<Vec<FileInfo>> {
-    read_dir_deep_(dir, deep, 0)
+    let options = WalkOptions {
+        max_depth: Some(deep),
+        order: WalkOrder::ListingFirst,
+        ..Default::default()
+    };
+    read_dir_deep_with(dir, options)
 }
 
-fn read_dir_deep_(dir: &str, max_deep: usize, deep: usize) -> Result<Vec<FileInfo>> {
-    if deep >= max_deep {
-        return Ok(Vec::new());
-    }
+/// `read_dir_deep` with walk options (query, ignore, etc...)
+pub fn read_dir_deep_with(dir: &str, options: WalkOptions) -> Result<Vec<FileInfo>> {
+    walk_with(dir, options)
+        .map(|entry| entry.map(|entry| entry.info))
+        .collect()
+}
 
-    let mut infos = read_dir(dir)?;
-    let next_deep = deep + 1;
-    if next_deep < max_deep {
-        let mut children = Vec::<FileInfo>::new();
-        let dirs: Vec<&FileInfo> = infos.iter().filter(|info| info.is_dir).collect();
-        for dir in &dirs {
-            let children_ = read_dir_deep_(&dir.path_string(), max_deep, next_deep)?;
-            children.extend(children_);
-        }
-        infos.extend(children);
-    }
-    return Ok(infos);
+/// `read_dir_deep_with` which skips unreadable directories and entries (`WalkOptions.lenient`).
+/// It fails only when `dir` itself can not be read.
+pub fn read_dir_deep_lenient(dir: &str, options: WalkOptions) -> Result<ReadDirReport> {
+    let mut walk = walk_with(
+        dir,
+        WalkOptions {
+            lenient: true,
+            ..options
+        },
+    );
+    let infos = walk
+        .by_ref()
+        .map(|entry| entry.map(|entry| entry.info))
+        .collect::<Result<Vec<FileInfo>>>()?;
+    Ok(ReadDirReport {
+        infos,
+        failures: walk.take_failures(),
+    })
 }
 
 /// File existence check
@@ -149,22 +299,20 @@ pub fn is_exists(path: &str) -> bool {
     Path::new(path).exists()
 }
 
+// 別デバイス間ではコピーして移動する。既存の to は上書き (他の方針は move_file_with)
 pub fn rename(from: &str, to: &str) -> Result<()> {
-    fs::rename(from, to).map_err(|e| anyhow!(e))
+    move_file_with(from, to, &MoveOptions::default(), |_| {})?;
+    Ok(())
 }
 
 pub fn remove_dir_all(path: &str) -> Result<()> {
     fs::remove_dir_all(path).map_err(|e| anyhow!(e))
 }
 
+// 書き込み途中で落ちると壊れるので、設定ファイルなどは write_with(.., &WriteOptions::atomic()) を使う
 pub fn write(path: PathBuf, data: &[u8]) -> Result<()> {
-    if let Some(dir) = path.parent() {
-        // ディレクトリ作成(create_dir_all は既存でもエラーにならない)
-        fs::create_dir_all(dir)?;
-    }
-
-    let mut file = File::create(path.as_path())?;
-    file.write_all(data).map_err(|e| anyhow!(e))
+    write_with(path, data, &WriteOptions::default())?;
+    Ok(())
 }
 
 // Move file or directory. It also fix dir if needed.
@@ -178,8 +326,9 @@ pub fn move_file(from: &str, to: &str) -> Result<()> {
         fs::create_dir_all(parent)?;
     }
 
-    // move file and dir with inner files
-    fs::rename(from, to).map_err(|e| anyhow!(e))
+    // move file and dir with inner files (copy between devices)
+    move_file_with(from, to, &MoveOptions::default(), |_| {})?;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -195,10 +344,106 @@ mod tests {
 
     #[test]
     fn test_write() {
-        let path: PathBuf = PathBuf::from("./test_file");
+        let path: PathBuf = PathBuf::from("./test_write_file");
         let data: &[u8] = b"Hello world";
-        let res = write(path, data);
+        let res = write(path.clone(), data);
         assert!(res.is_ok());
+        fs::remove_file(path).unwrap();
+    }
+
+    // 以前の再帰版と同じ順序 (中身をすべて返してから、子ディレクトリへ)
+    #[test]
+    fn test_read_dir_deep_order() {
+        fn read_dir_deep_recursive(dir: &str, max_deep: usize) -> Vec<FileInfo> {
+            let mut infos = read_dir(dir).unwrap();
+            if max_deep > 1 {
+                let dirs: Vec<String> = infos
+                    .iter()
+                    .filter(|info| info.is_dir)
+                    .map(|info| info.path_string())
+                    .collect();
+                for dir in dirs {
+                    infos.extend(read_dir_deep_recursive(&dir, max_deep - 1));
+                }
+            }
+            infos
+        }
+
+        let test_dir = "test_read_dir_deep_order";
+        for dir in ["a/aa/aaa", "a/ab", "b/ba", "c"] {
+            fs::create_dir_all(format!("{}/{}", test_dir, dir)).unwrap();
+            fs::write(format!("{}/{}/file.txt", test_dir, dir), b"test").unwrap();
+        }
+
+        for deep in [1, 2, 4] {
+            let paths: Vec<String> = read_dir_deep(test_dir, deep)
+                .unwrap()
+                .iter()
+                .map(|info| info.path_string())
+                .collect();
+            let expected: Vec<String> = read_dir_deep_recursive(test_dir, deep)
+                .iter()
+                .map(|info| info.path_string())
+                .collect();
+            assert_eq!(paths, expected);
+        }
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_dir_with_meta() {
+        let test_dir = "test_read_dir_with_meta";
+        fs::create_dir_all(format!("{}/sub", test_dir)).unwrap();
+        fs::write(format!("{}/a.txt", test_dir), b"abc").unwrap();
+
+        let options = ReadDirOptions { with_meta: true };
+        let infos = read_dir_with(test_dir, &options).unwrap();
+        assert_eq!(infos.len(), 2);
+        for info in infos {
+            let meta = info.meta.as_ref().unwrap();
+            let loaded = FileInfo::from_path(&info.path).load_meta().meta.unwrap();
+            assert_eq!(meta.size, loaded.size);
+            assert_eq!(meta.modified_time(), loaded.modified_time());
+        }
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_dir_deep_lenient() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let test_dir = "test_read_dir_deep_lenient";
+        let locked = format!("{}/a/locked", test_dir);
+        fs::create_dir_all(&locked).unwrap();
+        fs::create_dir_all(format!("{}/b", test_dir)).unwrap();
+        fs::write(format!("{}/x.txt", locked), b"x").unwrap();
+        fs::write(format!("{}/b/ok.txt", test_dir), b"ok").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let report = read_dir_deep_lenient(test_dir, WalkOptions::default()).unwrap();
+        let names: Vec<&str> = report
+            .infos
+            .iter()
+            .map(|info| info.file_name.as_str())
+            .collect();
+        assert!(names.contains(&"ok.txt"));
+        assert!(names.contains(&"locked"));
+        // root では読めてしまうので、読めない場合のみ
+        if fs::read_dir(&locked).is_err() {
+            assert_eq!(report.failures.len(), 1);
+            assert_eq!(report.failures[0].0, PathBuf::from(&locked));
+            assert!(read_dir_deep_with(test_dir, WalkOptions::default()).is_err());
+        }
+        assert!(read_dir_deep_lenient("notargetdir", WalkOptions::default()).is_err());
+
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        let report = read_dir_lenient(test_dir, &ReadDirOptions::default()).unwrap();
+        assert_eq!((report.infos.len(), report.failures.len()), (2, 0));
+
+        fs::remove_dir_all(test_dir).unwrap();
     }
 
     #[test]
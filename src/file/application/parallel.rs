@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+
+use crate::file::application::walk::{Level, Pending};
+use crate::file::{FileInfo, WalkEntry, WalkFailure, WalkOptions, WalkOrder};
+
+/// Parallel version of `read_dir_deep`.
+/// Sibling directories of the same level are read on `threads` workers (0: cpu count).
+/// The result is same as `read_dir_deep`, including the order.
+pub fn read_dir_deep_parallel(dir: &str, deep: usize, threads: usize) -> Result<Vec<FileInfo>> {
+    let options = WalkOptions {
+        max_depth: Some(deep),
+        order: WalkOrder::BreadthFirst,
+        ..Default::default()
+    };
+    let (entries, _) = walk_parallel(dir, options, threads)?;
+    Ok(listing_first(entries)
+        .into_iter()
+        .map(|entry| entry.info)
+        .collect())
+}
+
+// BreadthFirst の結果を read_dir_deep の順 (WalkOrder::ListingFirst) に並べ替える
+fn listing_first(entries: Vec<WalkEntry>) -> Vec<WalkEntry> {
+    let root = match entries.first() {
+        Some(entry) => entry.parent.clone(),
+        None => return entries,
+    };
+    let mut listings: HashMap<PathBuf, Vec<WalkEntry>> = HashMap::new();
+    for entry in entries {
+        listings
+            .entry(entry.parent.clone())
+            .or_default()
+            .push(entry);
+    }
+
+    let mut sorted = Vec::new();
+    let mut dirs = vec![root];
+    while let Some(dir) = dirs.pop() {
+        let Some(listing) = listings.remove(&dir) else {
+            continue;
+        };
+        dirs.extend(
+            listing
+                .iter()
+                .rev()
+                .filter(|entry| entry.info.is_dir)
+                .map(|entry| entry.info.path.clone()),
+        );
+        sorted.extend(listing);
+    }
+    sorted
+}
+
+/// Parallel version of `walk_with`. The order is always `BreadthFirst`.
+/// Directories and entries skipped by `WalkOptions.lenient` are returned as the failures
+/// (same as `Walk::take_failures`, always empty without `lenient`).
+pub fn walk_parallel(
+    dir: &str,
+    options: WalkOptions,
+    threads: usize,
+) -> Result<(Vec<WalkEntry>, Vec<WalkFailure>)> {
+    let threads = match threads {
+        0 => std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1),
+        n => n,
+    };
+
+    let mut entries: Vec<WalkEntry> = Vec::new();
+    let mut failures: Vec<WalkFailure> = Vec::new();
+    let mut pendings: Vec<Pending> = Pending::root(dir, &options).into_iter().collect();
+    while !pendings.is_empty() {
+        let levels = read_levels(pendings, &options, threads, &mut failures)?;
+
+        // 次の階層は、読んだ順番通りに並べることで逐次版と同じ順序になる
+        let mut next_pendings = Vec::new();
+        for mut level in levels {
+            failures.append(&mut level.failures);
+            while let Some(info) = level.next() {
+                let (entry, pending) = level.visit(&options, info);
+                entries.extend(entry);
+                next_pendings.extend(pending);
+            }
+        }
+        pendings = next_pendings;
+    }
+
+    Ok((entries, failures))
+}
+
+// read each dir on bounded workers and return listings in the same order as `pendings`
+fn read_levels(
+    pendings: Vec<Pending>,
+    options: &WalkOptions,
+    threads: usize,
+    failures: &mut Vec<WalkFailure>,
+) -> Result<Vec<Level>> {
+    let count = pendings.len();
+    let workers = threads.clamp(1, count.max(1));
+    let cursor = AtomicUsize::new(0);
+    let jobs: Vec<Mutex<Option<Pending>>> = pendings
+        .into_iter()
+        .map(|pending| Mutex::new(Some(pending)))
+        .collect();
+
+    let mut results: Vec<(usize, PathBuf, usize, Result<Level>)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = cursor.fetch_add(1, Ordering::Relaxed);
+                        let Some(job) = jobs.get(index) else {
+                            break;
+                        };
+                        let pending = job.lock().ok().and_then(|mut job| job.take());
+                        if let Some(pending) = pending {
+                            let (dir, depth) = (pending.dir.clone(), pending.depth);
+                            results.push((index, dir, depth, pending.read(options)));
+                        }
+                    }
+                    results
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_default())
+            .collect()
+    });
+
+    if results.len() != count {
+        return Err(anyhow!("Failed to read directories in worker thread"));
+    }
+
+    results.sort_by_key(|(index, _, _, _)| *index);
+    let mut levels = Vec::new();
+    for (_, dir, depth, result) in results {
+        match result {
+            Ok(level) => levels.push(level),
+            // lenient: 読めないサブディレクトリは飛ばして報告する
+            Err(e) if options.lenient && depth > 0 => failures.push((dir, e)),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::read_dir_deep;
+
+    #[test]
+    fn test_read_dir_deep_parallel() {
+        let test_dir = "test_read_dir_deep_parallel";
+        for i in 0..5 {
+            std::fs::create_dir_all(format!("{}/dir{}/sub", test_dir, i)).unwrap();
+            std::fs::write(format!("{}/dir{}/file.txt", test_dir, i), b"test").unwrap();
+            std::fs::write(format!("{}/dir{}/sub/file.txt", test_dir, i), b"test").unwrap();
+        }
+
+        let expected: Vec<String> = read_dir_deep(test_dir, 3)
+            .unwrap()
+            .iter()
+            .map(|info| info.path_string())
+            .collect();
+        let paths: Vec<String> = read_dir_deep_parallel(test_dir, 3, 3)
+            .unwrap()
+            .iter()
+            .map(|info| info.path_string())
+            .collect();
+        assert_eq!(paths.len(), 20);
+        assert_eq!(paths, expected);
+
+        assert!(read_dir_deep_parallel("notargetdir_parallel", 2, 2).is_err());
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_parallel_lenient() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let test_dir = "test_walk_parallel_lenient";
+        let locked = format!("{}/a/locked", test_dir);
+        std::fs::create_dir_all(&locked).unwrap();
+        std::fs::create_dir_all(format!("{}/b", test_dir)).unwrap();
+        std::fs::write(format!("{}/x.txt", locked), b"x").unwrap();
+        std::fs::write(format!("{}/b/ok.txt", test_dir), b"ok").unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+
+        let options = WalkOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let (entries, failures) = walk_parallel(test_dir, options.clone(), 2).unwrap();
+        assert!(entries.iter().any(|e| e.info.file_name == "ok.txt"));
+        // root では読めてしまうので、読めない場合のみ
+        if std::fs::read_dir(&locked).is_err() {
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].0, PathBuf::from(&locked));
+            assert!(walk_parallel(test_dir, WalkOptions::default(), 2).is_err());
+        }
+
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+}
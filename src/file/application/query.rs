@@ -0,0 +1,255 @@
+use std::ops::{Bound, RangeBounds};
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::file::application::glob::glob_match;
+use crate::file::{FileInfo, FileKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryKind {
+    Dir,
+    File,
+    Image,
+    Movie,
+    Zip,
+    Kind(FileKind),
+}
+
+impl QueryKind {
+    fn matches(&self, info: &FileInfo) -> bool {
+        match self {
+            QueryKind::Dir => info.is_dir,
+            QueryKind::File => info.is_file,
+            QueryKind::Image => info.is_image,
+            QueryKind::Movie => info.is_movie,
+            QueryKind::Zip => info.is_zip,
+            QueryKind::Kind(kind) => info.kind == *kind,
+        }
+    }
+}
+
+type U64Range = (Bound<u64>, Bound<u64>);
+
+/// Filter for listings.
+/// All predicates are AND. Predicates not set are not checked.
+/// e.g. `FileQuery::new().kind(QueryKind::Image).size(1024..).hidden(false).prune("node_modules")`
+#[derive(Debug, Clone, Default)]
+pub struct FileQuery {
+    kinds: Vec<QueryKind>,
+    extensions: Vec<String>,
+    name_globs: Vec<String>,
+    name_regex: Option<Regex>,
+    size: Option<U64Range>,
+    modified: Option<U64Range>,
+    created: Option<U64Range>,
+    hidden: Option<bool>,
+    symlink: Option<bool>,
+    prune_globs: Vec<String>,
+}
+
+impl FileQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match if one of kinds matches
+    pub fn kinds(mut self, kinds: &[QueryKind]) -> Self {
+        self.kinds.extend_from_slice(kinds);
+        self
+    }
+
+    pub fn kind(self, kind: QueryKind) -> Self {
+        self.kinds(&[kind])
+    }
+
+    /// Match if the extension is one of `extensions` (case insensitive, without ".")
+    pub fn extensions(mut self, extensions: &[&str]) -> Self {
+        self.extensions
+            .extend(extensions.iter().map(|ext| ext.to_lowercase()));
+        self
+    }
+
+    /// Match file name with glob. Match if one of globs matches
+    pub fn name_glob(mut self, glob: &str) -> Self {
+        self.name_globs.push(glob.to_string());
+        self
+    }
+
+    pub fn name_regex(mut self, regex: &str) -> Result<Self> {
+        self.name_regex = Some(Regex::new(regex)?);
+        Ok(self)
+    }
+
+    pub fn size(mut self, range: impl RangeBounds<u64>) -> Self {
+        self.size = Some(to_range(range));
+        self
+    }
+
+    /// Timestamp (seconds) range of modified time
+    pub fn modified(mut self, range: impl RangeBounds<u64>) -> Self {
+        self.modified = Some(to_range(range));
+        self
+    }
+
+    /// Timestamp (seconds) range of created time
+    pub fn created(mut self, range: impl RangeBounds<u64>) -> Self {
+        self.created = Some(to_range(range));
+        self
+    }
+
+    /// false: exclude hidden (".xxx") entries and don't descend into hidden dirs
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = Some(hidden);
+        self
+    }
+
+    /// false: exclude symlinks and don't descend into them
+    pub fn symlink(mut self, symlink: bool) -> Self {
+        self.symlink = Some(symlink);
+        self
+    }
+
+    /// Directories whose name matches the glob are not yielded nor descended into
+    pub fn prune(mut self, glob: &str) -> Self {
+        self.prune_globs.push(glob.to_string());
+        self
+    }
+
+    fn needs_meta(&self) -> bool {
+        self.size.is_some() || self.modified.is_some() || self.created.is_some()
+    }
+
+    /// Check the entry. Metadata is loaded only when a predicate needs it.
+    pub fn matches(&self, info: &mut FileInfo) -> bool {
+        if info.is_dir && self.is_pruned(info) {
+            return false;
+        }
+        if let Some(hidden) = self.hidden {
+            if is_hidden(info) != hidden {
+                return false;
+            }
+        }
+        if let Some(symlink) = self.symlink {
+            if info.is_symlink != symlink {
+                return false;
+            }
+        }
+        if !self.kinds.is_empty() && !self.kinds.iter().any(|kind| kind.matches(info)) {
+            return false;
+        }
+        if !self.extensions.is_empty() && !self.extensions.contains(&info.extension) {
+            return false;
+        }
+        if !self.name_globs.is_empty()
+            && !self
+                .name_globs
+                .iter()
+                .any(|glob| glob_match(glob, &info.file_name))
+        {
+            return false;
+        }
+        if let Some(regex) = &self.name_regex {
+            if !regex.is_match(&info.file_name) {
+                return false;
+            }
+        }
+
+        if self.needs_meta() {
+            // NOTE: io cost
+            if info.meta.is_none() {
+                info.load_meta();
+            }
+            let Some(meta) = &info.meta else {
+                return false;
+            };
+            let checks = [
+                (&self.size, meta.size),
+                (&self.modified, meta.modified),
+                (&self.created, meta.created),
+            ];
+            for (range, value) in checks {
+                if let Some(range) = range {
+                    if !range.contains(&value) {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Whether the walker should read inside the directory
+    pub fn should_descend(&self, info: &FileInfo) -> bool {
+        if self.hidden == Some(false) && is_hidden(info) {
+            return false;
+        }
+        if self.symlink == Some(false) && info.is_symlink {
+            return false;
+        }
+        !self.is_pruned(info)
+    }
+
+    fn is_pruned(&self, info: &FileInfo) -> bool {
+        self.prune_globs
+            .iter()
+            .any(|glob| glob_match(glob, &info.file_name))
+    }
+
+    /// Filter already listed infos
+    pub fn filter(&self, infos: Vec<FileInfo>) -> Vec<FileInfo> {
+        infos
+            .into_iter()
+            .filter_map(|mut info| self.matches(&mut info).then_some(info))
+            .collect()
+    }
+}
+
+fn to_range(range: impl RangeBounds<u64>) -> U64Range {
+    (range.start_bound().cloned(), range.end_bound().cloned())
+}
+
+fn is_hidden(info: &FileInfo) -> bool {
+    info.file_name.starts_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::{walk_with, WalkOptions};
+
+    #[test]
+    fn test_file_query() {
+        let test_dir = "test_file_query";
+        std::fs::create_dir_all(format!("{}/.hidden", test_dir)).unwrap();
+        std::fs::create_dir_all(format!("{}/pruned", test_dir)).unwrap();
+        std::fs::create_dir_all(format!("{}/sub", test_dir)).unwrap();
+        std::fs::write(format!("{}/.hidden/a.jpg", test_dir), b"test").unwrap();
+        std::fs::write(format!("{}/pruned/b.jpg", test_dir), b"test").unwrap();
+        std::fs::write(format!("{}/sub/c.jpg", test_dir), b"test").unwrap();
+        std::fs::write(format!("{}/sub/d.JPG", test_dir), b"").unwrap();
+        std::fs::write(format!("{}/sub/e.mp4", test_dir), b"test").unwrap();
+
+        let query = FileQuery::new()
+            .kind(QueryKind::Image)
+            .extensions(&["jpg"])
+            .size(1..)
+            .hidden(false)
+            .prune("prune*");
+        let options = WalkOptions {
+            query: Some(query),
+            ..Default::default()
+        };
+        let names: Vec<String> = walk_with(test_dir, options)
+            .map(|entry| entry.unwrap().info.file_name)
+            .collect();
+        assert_eq!(names, vec!["c.jpg"]);
+
+        let query = FileQuery::new().name_regex(r"^[a-e]\.").unwrap();
+        let infos = crate::file::read_dir(&format!("{}/sub", test_dir)).unwrap();
+        assert_eq!(query.filter(infos).len(), 3);
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+}
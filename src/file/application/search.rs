@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::file::text::normalize;
+use crate::file::{write_with, FileInfo, WriteOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// File name or a dir name starts with the query
+    Prefix,
+    /// File name or a dir name contains the query
+    Substring,
+    /// Characters of the query appear in order in the file name (ranked)
+    Fuzzy,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchHit<'a> {
+    pub info: &'a FileInfo,
+    /// Larger is better
+    pub score: i64,
+}
+
+#[derive(Debug, Clone)]
+struct IndexEntry {
+    info: FileInfo,
+    // 正規化済み
+    name: String,
+    components: Vec<String>,
+}
+
+impl IndexEntry {
+    fn new(info: FileInfo) -> Self {
+        let components = info
+            .dir
+            .components()
+            .map(|c| normalize(&c.as_os_str().to_string_lossy()))
+            .filter(|c| !c.is_empty())
+            .collect();
+        IndexEntry {
+            name: normalize(&info.file_name),
+            components,
+            info,
+        }
+    }
+
+    fn terms(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.name).chain(self.components.iter())
+    }
+}
+
+/// In-memory index of file names for "find file by name".
+/// Matching ignores case, full/half width and hiragana/katakana differences.
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    entries: HashMap<PathBuf, IndexEntry>,
+    // 正規化したファイル名/ディレクトリ名 -> path (前方一致用)
+    terms: BTreeMap<String, BTreeSet<PathBuf>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_infos(infos: impl IntoIterator<Item = FileInfo>) -> Self {
+        let mut index = SearchIndex::new();
+        for info in infos {
+            index.insert(info);
+        }
+        index
+    }
+
+    /// Add or replace (same path) an entry
+    pub fn insert(&mut self, info: FileInfo) {
+        self.remove(&info.path);
+        let entry = IndexEntry::new(info);
+        for term in entry.terms() {
+            self.terms
+                .entry(term.clone())
+                .or_default()
+                .insert(entry.info.path.clone());
+        }
+        self.entries.insert(entry.info.path.clone(), entry);
+    }
+
+    pub fn remove(&mut self, path: &Path) -> Option<FileInfo> {
+        let entry = self.entries.remove(path)?;
+        for term in entry.terms() {
+            if let Some(paths) = self.terms.get_mut(term) {
+                paths.remove(path);
+                if paths.is_empty() {
+                    self.terms.remove(term);
+                }
+            }
+        }
+        Some(entry.info)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&FileInfo> {
+        self.entries.get(path).map(|entry| &entry.info)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Hits sorted by score (best first). `limit` 0 means no limit.
+    pub fn search(&self, query: &str, mode: SearchMode, limit: usize) -> Vec<SearchHit<'_>> {
+        let query = normalize(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut hits: Vec<SearchHit> = match mode {
+            SearchMode::Prefix => self.search_prefix(&query),
+            SearchMode::Substring => self.scan(|entry| substring_score(&query, entry)),
+            SearchMode::Fuzzy => {
+                let query: Vec<char> = query.chars().collect();
+                self.scan(|entry| fuzzy_score(&query, &entry.name))
+            }
+        };
+
+        hits.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.info.file_name.cmp(&b.info.file_name))
+                .then_with(|| a.info.path.cmp(&b.info.path))
+        });
+        if limit > 0 {
+            hits.truncate(limit);
+        }
+        hits
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let infos: Vec<&FileInfo> = self.entries.values().map(|entry| &entry.info).collect();
+        let json = serde_json::to_vec(&infos)?;
+        // 途中で落ちても前の index が残るように
+        write_with(PathBuf::from(path), &json, &WriteOptions::atomic())
+            .with_context(|| format!("Failed to save. Path: {}", path))?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<SearchIndex> {
+        let json =
+            std::fs::read(path).with_context(|| format!("Failed to load. Path: {}", path))?;
+        let infos: Vec<FileInfo> = serde_json::from_slice(&json)?;
+        Ok(SearchIndex::from_infos(infos))
+    }
+
+    fn search_prefix(&self, query: &str) -> Vec<SearchHit<'_>> {
+        let mut scores: HashMap<&Path, i64> = HashMap::new();
+        for (term, paths) in self.terms.range(query.to_string()..) {
+            if !term.starts_with(query) {
+                break;
+            }
+            for path in paths {
+                let entry = &self.entries[path];
+                // ファイル名の一致を優先し、短い名前ほど上位
+                let score = if entry.name == *term {
+                    1000 - entry.name.chars().count() as i64
+                } else {
+                    100
+                };
+                let best = scores.entry(path.as_path()).or_insert(score);
+                *best = (*best).max(score);
+            }
+        }
+        scores
+            .into_iter()
+            .map(|(path, score)| SearchHit {
+                info: &self.entries[path].info,
+                score,
+            })
+            .collect()
+    }
+
+    fn scan(&self, score: impl Fn(&IndexEntry) -> Option<i64>) -> Vec<SearchHit<'_>> {
+        self.entries
+            .values()
+            .filter_map(|entry| {
+                score(entry).map(|score| SearchHit {
+                    info: &entry.info,
+                    score,
+                })
+            })
+            .collect()
+    }
+}
+
+fn substring_score(query: &str, entry: &IndexEntry) -> Option<i64> {
+    if let Some(pos) = entry.name.find(query) {
+        // 先頭に近いほど、短い名前ほど上位 (バイトではなく文字数で比べる)
+        let pos = entry.name[..pos].chars().count();
+        return Some(1000 - pos as i64 - entry.name.chars().count() as i64);
+    }
+    if entry.components.iter().any(|c| c.contains(query)) {
+        return Some(0);
+    }
+    None
+}
+
+// query の文字が順に現れれば一致。連続・単語の先頭での一致を高く評価する
+fn fuzzy_score(query: &[char], text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().collect();
+    let mut score: i64 = 0;
+    let mut text_pos = 0;
+    let mut last_match: Option<usize> = None;
+
+    for q in query {
+        let found = (text_pos..text.len()).find(|&i| text[i] == *q)?;
+        score += 1;
+        if last_match.is_some_and(|last| last + 1 == found) {
+            score += 5;
+        }
+        let at_boundary =
+            found == 0 || matches!(text[found - 1], ' ' | '_' | '-' | '.' | '(' | '[');
+        if at_boundary {
+            score += 3;
+        }
+        if let Some(last) = last_match {
+            score -= (found - last - 1) as i64;
+        }
+        last_match = Some(found);
+        text_pos = found + 1;
+    }
+    Some(score * 100 - text.len() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(hits: &[SearchHit]) -> Vec<String> {
+        hits.iter().map(|hit| hit.info.file_name.clone()).collect()
+    }
+
+    #[test]
+    fn test_search_index() {
+        let mut index = SearchIndex::from_infos(
+            [
+                "library/Photos/holiday.jpg",
+                "library/Photos/hollow.png",
+                "library/music/ほしのうた.mp3",
+                "library/docs/report_final.pdf",
+                "library/docs/readme.txt",
+            ]
+            .into_iter()
+            .map(FileInfo::from_str),
+        );
+        assert_eq!(index.len(), 5);
+
+        // prefix: file names and dir names
+        assert_eq!(
+            names(&index.search("hol", SearchMode::Prefix, 0)),
+            ["hollow.png", "holiday.jpg"]
+        );
+        assert_eq!(index.search("photos", SearchMode::Prefix, 0).len(), 2);
+
+        // kana / width insensitive
+        assert_eq!(
+            names(&index.search("ﾎｼ", SearchMode::Prefix, 0)),
+            ["ほしのうた.mp3"]
+        );
+        assert_eq!(
+            names(&index.search("ノウタ", SearchMode::Substring, 0)),
+            ["ほしのうた.mp3"]
+        );
+        assert_eq!(
+            names(&index.search("ＦＩＮＡＬ", SearchMode::Substring, 0)),
+            ["report_final.pdf"]
+        );
+
+        // fuzzy: ranked by consecutive / boundary matches
+        let hits = index.search("rfin", SearchMode::Fuzzy, 0);
+        assert_eq!(names(&hits)[0], "report_final.pdf");
+        assert!(index.search("zzz", SearchMode::Fuzzy, 0).is_empty());
+
+        // incremental update
+        index.insert(FileInfo::from_str("library/Photos/holy.jpg"));
+        index.remove(Path::new("library/Photos/hollow.png"));
+        assert_eq!(
+            names(&index.search("hol", SearchMode::Prefix, 1)),
+            ["holy.jpg"]
+        );
+
+        // substring: position and length are counted in chars (CJK is not penalised)
+        let cjk = SearchIndex::from_infos(
+            ["abcdefghijklx.txt", "あいうえおx.txt"]
+                .into_iter()
+                .map(FileInfo::from_str),
+        );
+        assert_eq!(
+            names(&cjk.search("x", SearchMode::Substring, 0)),
+            ["あいうえおx.txt", "abcdefghijklx.txt"]
+        );
+
+        let path = "test_search_index.json";
+        index.save(path).unwrap();
+        let loaded = SearchIndex::load(path).unwrap();
+        assert_eq!(loaded.len(), index.len());
+        assert_eq!(loaded.search("holi", SearchMode::Prefix, 0).len(), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+}
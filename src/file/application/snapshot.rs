@@ -0,0 +1,318 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::file::{file_hash, walk_with, WalkOptions};
+use crate::time::Timestamp;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotEntry {
+    /// Relative to the root of the snapshot
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: u64, // Timestamp
+    #[serde(default)]
+    pub modified_nsec: u32,
+    /// Content hash (blake3, hex). Only with `Snapshot::hash_files`
+    #[serde(default)]
+    pub hash: Option<String>,
+}
+
+// 2: modified_nsec を追加
+const SNAPSHOT_VERSION: u32 = 2;
+
+/// State of a tree at a time. Save it and `diff` it against a fresh scan later.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Snapshot {
+    /// Format version (0 for snapshots saved before versioning)
+    #[serde(default)]
+    pub version: u32,
+    pub root: PathBuf,
+    pub created: u64, // Timestamp
+    /// Sorted by path
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotChange {
+    pub old: SnapshotEntry,
+    pub new: SnapshotEntry,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SnapshotDiff {
+    pub added: Vec<SnapshotEntry>,
+    pub removed: Vec<SnapshotEntry>,
+    pub modified: Vec<SnapshotChange>,
+    pub moved: Vec<SnapshotChange>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+            && self.moved.is_empty()
+    }
+}
+
+impl Snapshot {
+    /// Scan `dir` (meta of every entry is loaded)
+    pub fn scan(dir: &str, options: WalkOptions) -> Result<Snapshot> {
+        let root = PathBuf::from(dir);
+        let mut entries: Vec<SnapshotEntry> = Vec::new();
+        let options = WalkOptions {
+            with_meta: true,
+            ..options
+        };
+        for entry in walk_with(dir, options) {
+            let mut info = entry?.info;
+            if info.meta.is_none() {
+                info.load_meta();
+            }
+            let meta = info.meta.unwrap_or_default();
+            let path = info
+                .path
+                .strip_prefix(&root)
+                .map(Path::to_path_buf)
+                .unwrap_or(info.path);
+            entries.push(SnapshotEntry {
+                path,
+                is_dir: info.is_dir,
+                size: if info.is_dir { 0 } else { meta.size },
+                modified: meta.modified,
+                modified_nsec: meta.modified_nsec,
+                hash: None,
+            });
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(Snapshot {
+            version: SNAPSHOT_VERSION,
+            root,
+            created: Timestamp::from_system_time(SystemTime::now()),
+            entries,
+        })
+    }
+
+    /// Compute content hashes of files, so that `diff` detects moves and changes by contents. (IO cost)
+    pub fn hash_files(&mut self) -> Result<()> {
+        for entry in self.entries.iter_mut().filter(|entry| !entry.is_dir) {
+            entry.hash = Some(file_hash(&self.root.join(&entry.path))?);
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let json = serde_json::to_vec_pretty(self)?;
+        std::fs::write(path, json).with_context(|| format!("Failed to save. Path: {}", path))
+    }
+
+    pub fn load(path: &str) -> Result<Snapshot> {
+        let json =
+            std::fs::read(path).with_context(|| format!("Failed to load. Path: {}", path))?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+
+    /// Changes from this snapshot to `newer`.
+    /// Removed and added files with the same hash (or the same size and modified time when not hashed) are reported as moved.
+    pub fn diff(&self, newer: &Snapshot) -> SnapshotDiff {
+        // 古い形式には modified_nsec がない (0) ので、秒まで比較
+        let nsec = self.version >= 2 && newer.version >= 2;
+        let old: BTreeMap<&Path, &SnapshotEntry> = self
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_path(), entry))
+            .collect();
+        let new: BTreeMap<&Path, &SnapshotEntry> = newer
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_path(), entry))
+            .collect();
+
+        let mut diff = SnapshotDiff::default();
+        for (path, old_entry) in &old {
+            match new.get(path) {
+                Some(new_entry) if new_entry.is_dir == old_entry.is_dir => {
+                    if !old_entry.is_dir && is_modified(old_entry, new_entry, nsec) {
+                        diff.modified.push(SnapshotChange {
+                            old: (*old_entry).clone(),
+                            new: (*new_entry).clone(),
+                        });
+                    }
+                }
+                Some(new_entry) => {
+                    // file <-> dir
+                    diff.removed.push((*old_entry).clone());
+                    diff.added.push((*new_entry).clone());
+                }
+                None => diff.removed.push((*old_entry).clone()),
+            }
+        }
+        for (path, new_entry) in &new {
+            if !old.contains_key(path) {
+                diff.added.push((*new_entry).clone());
+            }
+        }
+
+        detect_moves(&mut diff, nsec);
+        diff
+    }
+}
+
+fn is_modified(old: &SnapshotEntry, new: &SnapshotEntry, nsec: bool) -> bool {
+    if let (Some(old_hash), Some(new_hash)) = (&old.hash, &new.hash) {
+        return old_hash != new_hash;
+    }
+    old.size != new.size
+        || old.modified != new.modified
+        || (nsec && old.modified_nsec != new.modified_nsec)
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum MoveKey {
+    Hash(String),
+    SizeModified(u64, u64, u32),
+}
+
+fn move_key(entry: &SnapshotEntry, nsec: bool) -> MoveKey {
+    match &entry.hash {
+        Some(hash) => MoveKey::Hash(hash.clone()),
+        None => MoveKey::SizeModified(
+            entry.size,
+            entry.modified,
+            if nsec { entry.modified_nsec } else { 0 },
+        ),
+    }
+}
+
+// removed と added で同じ内容のファイルを moved にする
+fn detect_moves(diff: &mut SnapshotDiff, nsec: bool) {
+    let mut removed_by_key: HashMap<MoveKey, Vec<usize>> = HashMap::new();
+    for (i, entry) in diff.removed.iter().enumerate().rev() {
+        if !entry.is_dir {
+            removed_by_key
+                .entry(move_key(entry, nsec))
+                .or_default()
+                .push(i);
+        }
+    }
+
+    let mut moved_from: Vec<usize> = Vec::new();
+    let mut added: Vec<SnapshotEntry> = Vec::new();
+    for entry in std::mem::take(&mut diff.added) {
+        let from = if entry.is_dir {
+            None
+        } else {
+            removed_by_key
+                .get_mut(&move_key(&entry, nsec))
+                .and_then(|indexes| indexes.pop())
+        };
+        match from {
+            Some(i) => {
+                moved_from.push(i);
+                diff.moved.push(SnapshotChange {
+                    old: diff.removed[i].clone(),
+                    new: entry,
+                });
+            }
+            None => added.push(entry),
+        }
+    }
+    diff.added = added;
+
+    moved_from.sort_unstable();
+    for i in moved_from.into_iter().rev() {
+        diff.removed.remove(i);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_diff() {
+        let test_dir = "test_snapshot_diff";
+        std::fs::create_dir_all(format!("{}/sub", test_dir)).unwrap();
+        std::fs::write(format!("{}/keep.txt", test_dir), b"keep").unwrap();
+        std::fs::write(format!("{}/modify.txt", test_dir), b"before").unwrap();
+        std::fs::write(format!("{}/remove.txt", test_dir), b"remove").unwrap();
+        std::fs::write(format!("{}/move.txt", test_dir), b"move me").unwrap();
+
+        let mut before = Snapshot::scan(test_dir, WalkOptions::default()).unwrap();
+        before.hash_files().unwrap();
+        let snapshot_path = format!("{}.json", test_dir);
+        before.save(&snapshot_path).unwrap();
+
+        std::fs::write(format!("{}/modify.txt", test_dir), b"after!").unwrap();
+        std::fs::remove_file(format!("{}/remove.txt", test_dir)).unwrap();
+        std::fs::rename(
+            format!("{}/move.txt", test_dir),
+            format!("{}/sub/moved.txt", test_dir),
+        )
+        .unwrap();
+        std::fs::write(format!("{}/add.txt", test_dir), b"add").unwrap();
+
+        let before = Snapshot::load(&snapshot_path).unwrap();
+        let mut after = Snapshot::scan(test_dir, WalkOptions::default()).unwrap();
+        after.hash_files().unwrap();
+        let diff = before.diff(&after);
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].path, PathBuf::from("add.txt"));
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].path, PathBuf::from("remove.txt"));
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].new.path, PathBuf::from("modify.txt"));
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!(diff.moved[0].old.path, PathBuf::from("move.txt"));
+        assert_eq!(diff.moved[0].new.path, PathBuf::from("sub/moved.txt"));
+        assert!(after.diff(&after).is_empty());
+
+        std::fs::remove_file(snapshot_path).unwrap();
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_diff_old_format() {
+        let test_dir = "test_snapshot_diff_old_format";
+        std::fs::create_dir_all(format!("{}/sub", test_dir)).unwrap();
+        std::fs::write(format!("{}/keep.txt", test_dir), b"keep").unwrap();
+        std::fs::write(format!("{}/move.txt", test_dir), b"move me").unwrap();
+
+        // version と modified_nsec のない、以前の形式の JSON
+        let before = Snapshot::scan(test_dir, WalkOptions::default()).unwrap();
+        let mut json = serde_json::to_value(&before).unwrap();
+        json.as_object_mut().unwrap().remove("version");
+        for entry in json["entries"].as_array_mut().unwrap() {
+            entry.as_object_mut().unwrap().remove("modified_nsec");
+        }
+        let snapshot_path = format!("{}.json", test_dir);
+        std::fs::write(&snapshot_path, serde_json::to_vec(&json).unwrap()).unwrap();
+
+        std::fs::rename(
+            format!("{}/move.txt", test_dir),
+            format!("{}/sub/moved.txt", test_dir),
+        )
+        .unwrap();
+
+        let before = Snapshot::load(&snapshot_path).unwrap();
+        assert_eq!(before.version, 0);
+        let after = Snapshot::scan(test_dir, WalkOptions::default()).unwrap();
+        let diff = before.diff(&after);
+
+        assert!(diff.modified.is_empty());
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!(diff.moved[0].new.path, PathBuf::from("sub/moved.txt"));
+
+        std::fs::remove_file(snapshot_path).unwrap();
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+use anyhow::Result;
+use zip::ZipArchive;
+
+use crate::file::FileFormat;
+
+/// Bytes of the header needed by `detect_format`
+pub const SNIFF_LEN: usize = 64;
+
+// 中身は zip だが、アーカイブとしては扱わない形式 (拡張子の種類のまま)
+const ZIP_BASED_EXTENSIONS: &[&str] = &[
+    "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar", "apk", "xpi", "kmz", "3mf",
+];
+
+/// Zip based document formats (docx, epub, jar, ...). `FileInfo::sniff` keeps the kind of the extension for them.
+pub fn is_zip_based(extension: &str) -> bool {
+    ZIP_BASED_EXTENSIONS.contains(&extension)
+}
+
+/// Detect the format from the header bytes of a file. None if unknown.
+pub fn detect_format(header: &[u8]) -> Option<FileFormat> {
+    let starts = |magic: &[u8]| header.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+
+    if starts(&[0xFF, 0xD8, 0xFF]) {
+        return Some(FileFormat::Jpeg);
+    }
+    if starts(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        return Some(FileFormat::Png);
+    }
+    if starts(b"GIF87a") || starts(b"GIF89a") {
+        return Some(FileFormat::Gif);
+    }
+    if starts(b"RIFF") && at(8, b"WEBP") {
+        return Some(FileFormat::WebP);
+    }
+    if starts(b"RIFF") && at(8, b"AVI ") {
+        return Some(FileFormat::Avi);
+    }
+    if starts(b"II*\0") || starts(b"MM\0*") {
+        return Some(FileFormat::Tiff);
+    }
+    if at(4, b"ftyp") {
+        // ISO base media file: brand decides the format.
+        // Others (M4A audio, 3gp, cr3 raw, ...) are unknown, so the kind of the extension is kept.
+        return match header.get(8..12) {
+            Some(b"avif") | Some(b"avis") => Some(FileFormat::Avif),
+            Some(b"heic") | Some(b"heix") | Some(b"heim") | Some(b"heis") | Some(b"mif1")
+            | Some(b"msf1") => Some(FileFormat::Heic),
+            Some(b"qt  ") => Some(FileFormat::Mov),
+            Some(b"isom") | Some(b"iso2") | Some(b"mp41") | Some(b"mp42") | Some(b"avc1")
+            | Some(b"M4V ") => Some(FileFormat::Mp4),
+            _ => None,
+        };
+    }
+    if starts(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        // EBML: DocType is written in the header
+        let is_webm = header.windows(4).any(|w| w == b"webm");
+        return Some(if is_webm {
+            FileFormat::Webm
+        } else {
+            FileFormat::Mkv
+        });
+    }
+    if starts(&[0x00, 0x00, 0x01, 0xBA]) || starts(&[0x00, 0x00, 0x01, 0xB3]) {
+        return Some(FileFormat::Mpeg);
+    }
+    if starts(b"PK\x03\x04") || starts(b"PK\x05\x06") || starts(b"PK\x07\x08") {
+        return Some(FileFormat::Zip);
+    }
+    if starts(b"Rar!\x1A\x07") {
+        return Some(FileFormat::Rar);
+    }
+    if starts(&[b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C]) {
+        return Some(FileFormat::SevenZip);
+    }
+    if starts(&[0x1F, 0x8B]) {
+        return Some(FileFormat::Gzip);
+    }
+    if starts(b"%PDF-") {
+        return Some(FileFormat::Pdf);
+    }
+    if starts(b"BM") && header.len() >= 14 {
+        return Some(FileFormat::Bmp);
+    }
+    None
+}
+
+/// Read the header of a file and detect the format
+pub fn sniff_file(path: &str) -> Result<Option<FileFormat>> {
+    let file = File::open(path)?;
+    read_header(file)
+}
+
+/// Read the header of an entry in the zip and detect the format
+pub fn sniff_zip_entry(
+    archive: &mut ZipArchive<BufReader<File>>,
+    name: &str,
+) -> Result<Option<FileFormat>> {
+    let entry = archive.by_name(name)?;
+    read_header(entry)
+}
+
+fn read_header(reader: impl Read) -> Result<Option<FileFormat>> {
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    reader.take(SNIFF_LEN as u64).read_to_end(&mut header)?;
+    Ok(detect_format(&header))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::FileInfo;
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(
+            detect_format(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            Some(FileFormat::Jpeg)
+        );
+        assert_eq!(
+            detect_format(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(FileFormat::WebP)
+        );
+        assert_eq!(detect_format(b"\0\0\0\x18ftypisom"), Some(FileFormat::Mp4));
+        assert_eq!(detect_format(b"\0\0\0\x18ftypavif"), Some(FileFormat::Avif));
+        assert_eq!(detect_format(b"\0\0\0\x18ftypM4V "), Some(FileFormat::Mp4));
+        assert_eq!(detect_format(b"\0\0\0\x18ftypM4A "), None);
+        assert_eq!(detect_format(b"\0\0\0\x18ftypcrx "), None);
+        assert_eq!(detect_format(b"PK\x03\x04"), Some(FileFormat::Zip));
+        assert_eq!(detect_format(b"hello"), None);
+    }
+
+    #[test]
+    fn test_sniff() {
+        let test_dir = "test_sniff";
+        std::fs::create_dir_all(test_dir).unwrap();
+        // webp labeled as jpg, zip without extension, unknown contents
+        std::fs::write(format!("{}/a.jpg", test_dir), b"RIFF\0\0\0\0WEBPVP8 ").unwrap();
+        std::fs::copy("tests/data/sample.zip", format!("{}/archive", test_dir)).unwrap();
+        std::fs::write(format!("{}/b.png", test_dir), b"unknown").unwrap();
+
+        let info = FileInfo::from_str(&format!("{}/a.jpg", test_dir)).sniff();
+        assert_eq!(info.detected_format, Some(FileFormat::WebP));
+        assert_eq!(info.mime(), Some("image/webp"));
+        assert!(info.is_image);
+
+        let info = FileInfo::from_str(&format!("{}/archive", test_dir)).sniff();
+        assert!(info.is_zip);
+
+        // zip based documents stay documents
+        std::fs::copy("tests/data/sample.zip", format!("{}/doc.docx", test_dir)).unwrap();
+        let info = FileInfo::from_str(&format!("{}/doc.docx", test_dir)).sniff();
+        assert!(!info.is_zip);
+        assert_eq!(info.kind, crate::file::FileKind::Document);
+
+        // m4a stays audio
+        std::fs::write(format!("{}/a.m4a", test_dir), b"\0\0\0\x18ftypM4A ").unwrap();
+        let info = FileInfo::from_str(&format!("{}/a.m4a", test_dir)).sniff();
+        assert_eq!(info.kind, crate::file::FileKind::Audio);
+
+        let info = FileInfo::from_str(&format!("{}/b.png", test_dir)).sniff();
+        assert_eq!(info.detected_format, None);
+        assert!(info.is_image);
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+}
@@ -0,0 +1,337 @@
+use std::cmp::Ordering;
+use std::path::Path;
+
+use crate::file::application::text::normalize;
+use crate::file::{FileEntry, FileInfo, OptionPathUtil, PathUtil, ZipInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// File name in natural order
+    Name,
+    /// Full path in natural order
+    Path,
+    /// Directories before files (Desc: after files)
+    DirsFirst,
+    Extension,
+    Size,
+    Modified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortBy {
+    pub key: SortKey,
+    pub order: SortOrder,
+}
+
+impl SortBy {
+    pub fn asc(key: SortKey) -> Self {
+        SortBy {
+            key,
+            order: SortOrder::Asc,
+        }
+    }
+
+    pub fn desc(key: SortKey) -> Self {
+        SortBy {
+            key,
+            order: SortOrder::Desc,
+        }
+    }
+}
+
+/// Values used for sorting. Missing values (e.g. meta not loaded) are treated as 0.
+pub trait Sortable {
+    fn sort_name(&self) -> String;
+    fn sort_path(&self) -> String;
+    fn sort_is_dir(&self) -> bool;
+    fn sort_extension(&self) -> String;
+    fn sort_size(&self) -> u64;
+    fn sort_modified(&self) -> u64;
+}
+
+impl Sortable for FileInfo {
+    fn sort_name(&self) -> String {
+        self.file_name.clone()
+    }
+
+    fn sort_path(&self) -> String {
+        self.path_string()
+    }
+
+    fn sort_is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    fn sort_extension(&self) -> String {
+        self.extension.clone()
+    }
+
+    fn sort_size(&self) -> u64 {
+        match (&self.meta, &self.zip_info) {
+            (Some(meta), _) => meta.size,
+            (None, Some(zip_info)) => zip_info.size,
+            (None, None) => 0,
+        }
+    }
+
+    fn sort_modified(&self) -> u64 {
+        self.meta.as_ref().map(|meta| meta.modified).unwrap_or(0)
+    }
+}
+
+impl Sortable for ZipInfo {
+    fn sort_name(&self) -> String {
+        self.name
+            .remove_ends_separator()
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn sort_path(&self) -> String {
+        self.name.remove_ends_separator()
+    }
+
+    fn sort_is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    fn sort_extension(&self) -> String {
+        if !self.is_file {
+            return String::new();
+        }
+        Path::new(&self.name)
+            .extension()
+            .to_string_ex()
+            .to_lowercase()
+    }
+
+    fn sort_size(&self) -> u64 {
+        self.size
+    }
+
+    fn sort_modified(&self) -> u64 {
+        0
+    }
+}
+
+impl<T> Sortable for FileEntry<T> {
+    fn sort_name(&self) -> String {
+        self.info.sort_name()
+    }
+
+    fn sort_path(&self) -> String {
+        self.info.sort_path()
+    }
+
+    fn sort_is_dir(&self) -> bool {
+        self.info.sort_is_dir()
+    }
+
+    fn sort_extension(&self) -> String {
+        self.info.sort_extension()
+    }
+
+    fn sort_size(&self) -> u64 {
+        self.info.sort_size()
+    }
+
+    fn sort_modified(&self) -> u64 {
+        self.info.sort_modified()
+    }
+}
+
+/// Compare strings in natural order. e.g. "2" < "10", "ｐａｇｅ２" < "page10", "あ" == "ア"
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    NaturalKey::new(a).cmp(&NaturalKey::new(b))
+}
+
+// normalize 済みの文字列を持つ natural order のキー (ソートでは要素ごとに1回だけ作る)
+#[derive(Debug, PartialEq, Eq)]
+struct NaturalKey {
+    normalized: String,
+    original: String,
+}
+
+impl NaturalKey {
+    fn new(s: &str) -> Self {
+        NaturalKey {
+            normalized: normalize(s),
+            original: s.to_string(),
+        }
+    }
+}
+
+impl Ord for NaturalKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        natural_cmp_normalized(&self.normalized, &other.normalized)
+            .then_with(|| self.original.cmp(&other.original))
+    }
+}
+
+impl PartialOrd for NaturalKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn natural_cmp_normalized(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let da = take_digits(&mut a);
+                let db = take_digits(&mut b);
+                let (ta, tb) = (da.trim_start_matches('0'), db.trim_start_matches('0'));
+                let ordering = ta
+                    .len()
+                    .cmp(&tb.len())
+                    .then_with(|| ta.cmp(tb))
+                    // "01" < "1" の順で安定させる
+                    .then_with(|| db.len().cmp(&da.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                if ca != cb {
+                    return ca.cmp(&cb);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    digits
+}
+
+// SortBy 1つ分の値。同じ位置には同じ種類の値が入る
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum KeyValue {
+    Natural(NaturalKey),
+    Text(String),
+    Number(u64),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct SortValue {
+    value: KeyValue,
+    order: SortOrder,
+}
+
+impl Ord for SortValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let ordering = self.value.cmp(&other.value);
+        match self.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    }
+}
+
+impl PartialOrd for SortValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn sort_value<T: Sortable>(item: &T, sort_by: &SortBy) -> SortValue {
+    let value = match sort_by.key {
+        SortKey::Name => KeyValue::Natural(NaturalKey::new(&item.sort_name())),
+        SortKey::Path => KeyValue::Natural(NaturalKey::new(&item.sort_path())),
+        // dir (0) が先
+        SortKey::DirsFirst => KeyValue::Number(!item.sort_is_dir() as u64),
+        SortKey::Extension => KeyValue::Text(item.sort_extension()),
+        SortKey::Size => KeyValue::Number(item.sort_size()),
+        SortKey::Modified => KeyValue::Number(item.sort_modified()),
+    };
+    SortValue {
+        value,
+        order: sort_by.order,
+    }
+}
+
+pub trait SortUtil {
+    /// Sort by keys in priority order. The sort is stable.
+    fn sort_by_keys(&mut self, keys: &[SortBy]);
+
+    /// Directories first, then natural order of name
+    fn sort_natural(&mut self) {
+        self.sort_by_keys(&[SortBy::asc(SortKey::DirsFirst), SortBy::asc(SortKey::Name)]);
+    }
+}
+
+impl<T: Sortable> SortUtil for [T] {
+    fn sort_by_keys(&mut self, keys: &[SortBy]) {
+        // キーは要素ごとに1回だけ作る (normalize は重い)
+        self.sort_by_cached_key(|item| {
+            keys.iter()
+                .map(|sort_by| sort_value(item, sort_by))
+                .collect::<Vec<SortValue>>()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec!["10.jpg", "2.jpg", "1.jpg", "11.jpg", "０３.jpg", "01.jpg"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["01.jpg", "1.jpg", "2.jpg", "０３.jpg", "10.jpg", "11.jpg"]
+        );
+
+        assert_eq!(natural_cmp("第2話", "第10話"), Ordering::Less);
+        assert_eq!(natural_cmp("かばん2", "カバン10"), Ordering::Less);
+        assert_eq!(natural_cmp("ﾍﾟｰｼﾞ9", "ページ10"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_sort_by_keys() {
+        let mut infos: Vec<FileInfo> = ["b/10.png", "b/2.jpg", "a/", "b/1.png"]
+            .iter()
+            .map(|path| FileInfo::from_str(path))
+            .collect();
+
+        infos.sort_natural();
+        let names: Vec<&str> = infos.iter().map(|info| info.file_name.as_str()).collect();
+        assert_eq!(names, vec!["a", "1.png", "2.jpg", "10.png"]);
+
+        infos.sort_by_keys(&[SortBy::asc(SortKey::Extension), SortBy::desc(SortKey::Name)]);
+        let names: Vec<&str> = infos.iter().map(|info| info.file_name.as_str()).collect();
+        assert_eq!(names, vec!["a", "2.jpg", "10.png", "1.png"]);
+    }
+
+    #[test]
+    fn test_sort_zip_infos() {
+        let (_, mut infos) = crate::file::zip_util::ZipUtil::read("tests/data/sample.zip").unwrap();
+        infos.sort_by_keys(&[SortBy::asc(SortKey::Path)]);
+        let paths: Vec<String> = infos.iter().map(|info| info.sort_path()).collect();
+        let mut sorted = paths.clone();
+        sorted.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(paths, sorted);
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::file::{walk_with, CancelToken, FileEntry, FileInfo, FileKind, Walk, WalkOptions};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DirStats {
+    pub total_bytes: u64,
+    pub file_count: usize,
+    pub dir_count: usize,
+    /// Number of files per kind
+    pub kind_counts: HashMap<FileKind, usize>,
+    pub newest_modified: Option<u64>,
+    pub oldest_modified: Option<u64>,
+    /// Deepest depth of entries (0: direct children)
+    pub max_depth: usize,
+}
+
+impl DirStats {
+    /// Add an entry found at `depth`. Meta is loaded if not yet (IO cost).
+    pub fn add(&mut self, info: &mut FileInfo, depth: usize) {
+        self.max_depth = self.max_depth.max(depth);
+        if info.is_dir {
+            self.dir_count += 1;
+            return;
+        }
+
+        if info.meta.is_none() && !info.in_zip() {
+            info.load_meta();
+        }
+        self.file_count += 1;
+        *self.kind_counts.entry(info.kind).or_default() += 1;
+        if let Some(meta) = &info.meta {
+            self.total_bytes += meta.size;
+            self.add_modified(meta.modified);
+        } else if let Some(zip_info) = &info.zip_info {
+            self.total_bytes += zip_info.size;
+        }
+    }
+
+    /// Merge stats of a sub directory. `depth` is the depth of the sub directory itself.
+    pub fn merge(&mut self, other: &DirStats, depth: usize) {
+        self.total_bytes += other.total_bytes;
+        self.file_count += other.file_count;
+        self.dir_count += other.dir_count;
+        for (kind, count) in &other.kind_counts {
+            *self.kind_counts.entry(*kind).or_default() += count;
+        }
+        if let Some(modified) = other.newest_modified {
+            self.add_modified(modified);
+        }
+        if let Some(modified) = other.oldest_modified {
+            self.add_modified(modified);
+        }
+        if other.file_count + other.dir_count > 0 {
+            self.max_depth = self.max_depth.max(other.max_depth + depth + 1);
+        }
+    }
+
+    pub fn count(&self, kind: FileKind) -> usize {
+        self.kind_counts.get(&kind).copied().unwrap_or(0)
+    }
+
+    fn add_modified(&mut self, modified: u64) {
+        self.newest_modified = Some(self.newest_modified.map_or(modified, |m| m.max(modified)));
+        self.oldest_modified = Some(self.oldest_modified.map_or(modified, |m| m.min(modified)));
+    }
+}
+
+/// Collect stats step by step, so that callers can show progress or stop.
+pub struct DirStatsCollector {
+    walk: Walk,
+    stats: DirStats,
+    finished: bool,
+}
+
+impl DirStatsCollector {
+    pub fn new(dir: &str, options: WalkOptions) -> Self {
+        DirStatsCollector {
+            // meta は読み込みと同時に取得
+            walk: walk_with(
+                dir,
+                WalkOptions {
+                    with_meta: true,
+                    ..options
+                },
+            ),
+            stats: DirStats::default(),
+            finished: false,
+        }
+    }
+
+    /// Process up to `count` entries. Returns false when the walk is finished.
+    pub fn step(&mut self, count: usize) -> Result<bool> {
+        for _ in 0..count {
+            match self.walk.next() {
+                Some(entry) => {
+                    let mut entry = entry?;
+                    self.stats.add(&mut entry.info, entry.depth);
+                }
+                None => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+        Ok(!self.finished)
+    }
+
+    /// Stats collected so far
+    pub fn stats(&self) -> &DirStats {
+        &self.stats
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn into_stats(self) -> DirStats {
+        self.stats
+    }
+}
+
+// entries processed between progress callbacks
+const PROGRESS_INTERVAL: usize = 1000;
+
+/// Collect stats of all entries under `dir`.
+/// `on_progress` is called with the stats so far, and the walk stops with an error when `cancel` is set.
+pub fn dir_stats(
+    dir: &str,
+    options: WalkOptions,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(&DirStats),
+) -> Result<DirStats> {
+    let mut collector = DirStatsCollector::new(dir, options);
+    loop {
+        if cancel.is_cancelled() {
+            return Err(anyhow!("Cancelled. Path: {}", dir));
+        }
+        if !collector.step(PROGRESS_INTERVAL)? {
+            break;
+        }
+        on_progress(collector.stats());
+    }
+    Ok(collector.into_stats())
+}
+
+/// Compute stats of every node bottom-up and pass them to `f`.
+/// Stats of a node cover its descendants (not the node itself). Returns the stats of `entry`.
+pub fn annotate_stats<T>(
+    entry: &mut FileEntry<T>,
+    f: &mut impl FnMut(&mut T, &DirStats),
+) -> DirStats {
+    let mut stats = DirStats::default();
+    for child in &mut entry.children {
+        stats.add(&mut child.info, 0);
+        let child_stats = annotate_stats(child, f);
+        stats.merge(&child_stats, 0);
+    }
+    f(&mut entry.meta, &stats);
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::build_tree;
+
+    fn create_tree(test_dir: &str) {
+        std::fs::create_dir_all(format!("{}/sub/deep", test_dir)).unwrap();
+        std::fs::write(format!("{}/a.jpg", test_dir), b"1234").unwrap();
+        std::fs::write(format!("{}/sub/b.png", test_dir), b"12").unwrap();
+        std::fs::write(format!("{}/sub/deep/c.mp4", test_dir), b"123456").unwrap();
+    }
+
+    #[test]
+    fn test_dir_stats() {
+        let test_dir = "test_dir_stats";
+        create_tree(test_dir);
+
+        let stats = dir_stats(
+            test_dir,
+            WalkOptions::default(),
+            &CancelToken::new(),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(stats.total_bytes, 12);
+        assert_eq!(stats.file_count, 3);
+        assert_eq!(stats.dir_count, 2);
+        assert_eq!(stats.count(FileKind::Image), 2);
+        assert_eq!(stats.count(FileKind::Movie), 1);
+        assert_eq!(stats.max_depth, 2);
+        assert!(stats.newest_modified.is_some());
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let mut collector = DirStatsCollector::new(test_dir, WalkOptions::default());
+        assert!(collector.step(1).unwrap());
+        assert_eq!(
+            collector.stats().file_count + collector.stats().dir_count,
+            1
+        );
+        assert!(dir_stats(test_dir, WalkOptions::default(), &cancel, |_| {}).is_err());
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_annotate_stats() {
+        let test_dir = "test_annotate_stats";
+        create_tree(test_dir);
+
+        let mut tree =
+            build_tree(test_dir, WalkOptions::default(), |_| DirStats::default()).unwrap();
+        let stats = annotate_stats(&mut tree, &mut |meta, stats| *meta = stats.clone());
+        assert_eq!(stats.total_bytes, 12);
+        assert_eq!(tree.meta, stats);
+        assert_eq!(stats.max_depth, 2);
+
+        let sub = std::path::PathBuf::from(test_dir).join("sub");
+        let sub = tree.find(&sub).unwrap();
+        assert_eq!(sub.meta.total_bytes, 8);
+        assert_eq!(sub.meta.file_count, 2);
+        assert_eq!(sub.meta.max_depth, 1);
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+// 文字列比較用の正規化
+// 全角英数 -> 半角, 半角カナ -> 全角カナ (濁点の結合を含む), ひらがな -> カタカナ, 大文字 -> 小文字
+
+const HALF_WIDTH_KANA: &str = "ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン";
+const VOICEABLE: &str = "カキクケコサシスセソタチツテトハヒフヘホ";
+const SEMI_VOICEABLE: &str = "ハヒフヘホ";
+
+/// Normalize width, kana and case so that "ＡＢＣ１", "abc1", "ｱｲｳ", "あいう" and "アイウ" are compared as equal.
+pub(crate) fn normalize(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            // 濁点・半濁点は直前の文字と結合する
+            '\u{FF9E}' | '\u{309B}' | '\u{3099}' => {
+                if let Some(last) = out.pop() {
+                    out.push(voiced(last).unwrap_or(last));
+                }
+            }
+            '\u{FF9F}' | '\u{309C}' | '\u{309A}' => {
+                if let Some(last) = out.pop() {
+                    out.push(semi_voiced(last).unwrap_or(last));
+                }
+            }
+            c => out.extend(fold_char(c).to_lowercase()),
+        }
+    }
+    out
+}
+
+fn fold_char(c: char) -> char {
+    match c {
+        // 全角英数記号
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        '\u{3000}' => ' ',
+        // 半角カナ
+        '\u{FF66}'..='\u{FF9D}' => HALF_WIDTH_KANA
+            .chars()
+            .nth((c as u32 - 0xFF66) as usize)
+            .unwrap_or(c),
+        // ひらがな
+        '\u{3041}'..='\u{3096}' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+        c => c,
+    }
+}
+
+fn voiced(c: char) -> Option<char> {
+    if c == 'ウ' {
+        return Some('ヴ');
+    }
+    if VOICEABLE.contains(c) {
+        return char::from_u32(c as u32 + 1);
+    }
+    None
+}
+
+fn semi_voiced(c: char) -> Option<char> {
+    if SEMI_VOICEABLE.contains(c) {
+        return char::from_u32(c as u32 + 2);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("ＡＢＣ１２"), "abc12");
+        assert_eq!(normalize("ﾊﾟﾝﾀﾞ"), "パンダ");
+        assert_eq!(normalize("ぱんだ"), "パンダ");
+        assert_eq!(normalize("漢字"), "漢字");
+    }
+}
@@ -0,0 +1,579 @@
+use std::fs::{self, File, FileTimes, Metadata};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::file::{
+    CancelToken, ConflictOutcome, ConflictPolicy, ItemOutcome, PathUtil, RenameItem,
+};
+
+// コピー時に一度に読み書きするサイズ (進捗・キャンセルの単位)
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Default)]
+pub struct MoveProgress {
+    pub total_bytes: u64,
+    pub copied_bytes: u64,
+    pub total_files: usize,
+    pub copied_files: usize,
+    /// Source path being copied
+    pub current: PathBuf,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MoveOptions {
+    /// When the destination already exists (default `Overwrite`, same as `fs::rename`)
+    pub conflict: ConflictPolicy,
+    pub cancel: CancelToken,
+}
+
+/// Move a file or dir. When `fs::rename` fails because of different devices (EXDEV),
+/// it is copied, verified and then the source is deleted.
+/// `on_progress` is called only while copying. Returns what was done.
+pub fn move_file_with(
+    from: &str,
+    to: &str,
+    options: &MoveOptions,
+    on_progress: impl FnMut(&MoveProgress),
+) -> Result<ConflictOutcome> {
+    let source = fs::symlink_metadata(from)
+        .with_context(|| format!("Source path does not exist. Path: {}", from))?;
+    let (dest, outcome) = options
+        .conflict
+        .resolve(Path::new(to), source.modified().ok())?;
+    let Some(dest) = dest else {
+        return Ok(outcome);
+    };
+    // Overwrite でも先に消さない (置き換えは rename 自身に任せる)
+    match fs::rename(from, &dest) {
+        Ok(()) => Ok(outcome),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            move_by_copy(from, &dest.to_string_ex(), &options.cancel, on_progress)?;
+            Ok(outcome)
+        }
+        Err(e) => Err(anyhow!(
+            "Failed to move. From: {}, To: {}, {}",
+            from,
+            dest.to_string_ex(),
+            e
+        )),
+    }
+}
+
+/// Move several items (parent dirs of the destinations are created).
+/// It does not stop at an error, the outcome of each item is returned.
+pub fn move_files(items: &[RenameItem], options: &MoveOptions) -> Vec<ItemOutcome> {
+    items
+        .iter()
+        .map(|item| {
+            let result = (|| -> Result<ConflictOutcome> {
+                if options.cancel.is_cancelled() {
+                    return Err(anyhow!("Cancelled. Path: {}", item.from.to_string_ex()));
+                }
+                if let Some(parent) = item.to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                move_file_with(
+                    &item.from.to_string_ex(),
+                    &item.to.to_string_ex(),
+                    options,
+                    |_| {},
+                )
+            })();
+            let outcome = result.unwrap_or_else(|e| ConflictOutcome::Failed(e.to_string()));
+            ItemOutcome {
+                from: item.from.clone(),
+                to: match &outcome {
+                    ConflictOutcome::KeptBoth(path) => path.clone(),
+                    _ => item.to.clone(),
+                },
+                outcome,
+            }
+        })
+        .collect()
+}
+
+/// Move by copy + verify + delete (the fallback of `move_file_with`).
+/// Contents are copied into a temporary name next to `to` and renamed at the end, so `to` is not touched when it fails
+/// or is cancelled. Permissions and modified/accessed times are preserved.
+/// Trees with special files (FIFO, socket, device) fail before anything is copied.
+pub fn move_by_copy(
+    from: &str,
+    to: &str,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(&MoveProgress),
+) -> Result<()> {
+    let from = Path::new(from);
+    let to = Path::new(to);
+    let items = scan(from)?;
+    check_replaceable(&items[0], to)?;
+
+    let mut progress = MoveProgress {
+        total_bytes: items.iter().map(|item| item.size).sum(),
+        total_files: items
+            .iter()
+            .filter(|item| item.kind == ItemKind::File)
+            .count(),
+        ..Default::default()
+    };
+
+    let temp = temp_path(to);
+    let result = copy_items(from, &temp, &items, cancel, &mut progress, &mut on_progress);
+    if let Err(e) = result {
+        let _ = remove_any(&temp);
+        return Err(e);
+    }
+
+    fs::rename(&temp, to).inspect_err(|_| {
+        let _ = remove_any(&temp);
+    })?;
+    remove_any(from)
+}
+
+// 最後の rename で置き換えられるかを、コピーの前に確かめる
+// ディレクトリは上書きしない。ファイルとディレクトリの入れ替えもしない (fs::rename と同じくエラー)
+fn check_replaceable(source: &Item, to: &Path) -> Result<()> {
+    let Ok(dest) = fs::symlink_metadata(to) else {
+        return Ok(());
+    };
+    if source.kind == ItemKind::Dir || dest.is_dir() {
+        return Err(anyhow!("Already exists. Path: {}", to.to_string_ex()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ItemKind {
+    Dir,
+    File,
+    Symlink,
+}
+
+#[derive(Debug)]
+struct Item {
+    // from からの相対パス (from 自身は空)
+    relative: PathBuf,
+    kind: ItemKind,
+    size: u64,
+    meta: Metadata,
+}
+
+// 親ディレクトリが先に来る順で列挙
+fn scan(from: &Path) -> Result<Vec<Item>> {
+    let meta = fs::symlink_metadata(from)
+        .with_context(|| format!("Source path does not exist. Path: {}", from.to_string_ex()))?;
+    let mut items = vec![item(from, PathBuf::new(), meta)?];
+    let mut i = 0;
+    while i < items.len() {
+        if items[i].kind == ItemKind::Dir {
+            let dir = from.join(&items[i].relative);
+            let mut children: Vec<Item> = Vec::new();
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let relative = items[i].relative.join(entry.file_name());
+                children.push(item(from, relative, fs::symlink_metadata(entry.path())?)?);
+            }
+            items.extend(children);
+        }
+        i += 1;
+    }
+    Ok(items)
+}
+
+// FIFO / socket / device はコピーできない (FIFO は読むと止まる) ので、コピーを始める前にエラー
+fn item(from: &Path, relative: PathBuf, meta: Metadata) -> Result<Item> {
+    let file_type = meta.file_type();
+    let kind = if file_type.is_symlink() {
+        ItemKind::Symlink
+    } else if file_type.is_dir() {
+        ItemKind::Dir
+    } else if file_type.is_file() {
+        ItemKind::File
+    } else {
+        return Err(anyhow!(
+            "Special files can not be copied. Path: {}",
+            from.join(&relative).to_string_ex()
+        ));
+    };
+    Ok(Item {
+        relative,
+        size: if kind == ItemKind::File {
+            meta.len()
+        } else {
+            0
+        },
+        kind,
+        meta,
+    })
+}
+
+fn copy_items(
+    from: &Path,
+    to: &Path,
+    items: &[Item],
+    cancel: &CancelToken,
+    progress: &mut MoveProgress,
+    on_progress: &mut impl FnMut(&MoveProgress),
+) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    for item in items {
+        if cancel.is_cancelled() {
+            return Err(anyhow!("Cancelled. Path: {}", from.to_string_ex()));
+        }
+        let src = from.join(&item.relative);
+        let dst = to.join(&item.relative);
+        progress.current = src.clone();
+        match item.kind {
+            ItemKind::Dir => fs::create_dir(&dst)?,
+            ItemKind::Symlink => copy_symlink(&src, &dst)?,
+            ItemKind::File => {
+                copy_file(&src, &dst, cancel, progress, on_progress)?;
+                progress.copied_files += 1;
+                on_progress(progress);
+            }
+        }
+    }
+
+    // 中身を作るとディレクトリの更新日時が変わるので、最後に深い方から戻す
+    for item in items.iter().rev() {
+        if item.kind != ItemKind::Symlink {
+            preserve_meta(
+                &to.join(&item.relative),
+                &item.meta,
+                item.kind == ItemKind::Dir,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn copy_file(
+    src: &Path,
+    dst: &Path,
+    cancel: &CancelToken,
+    progress: &mut MoveProgress,
+    on_progress: &mut impl FnMut(&MoveProgress),
+) -> Result<()> {
+    let mut reader = File::open(src)?;
+    let mut writer = File::create(dst)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        if cancel.is_cancelled() {
+            return Err(anyhow!("Cancelled. Path: {}", src.to_string_ex()));
+        }
+        let len = reader.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        writer.write_all(&buf[..len])?;
+        hasher.update(&buf[..len]);
+        progress.copied_bytes += len as u64;
+        on_progress(progress);
+    }
+    writer.sync_all()?;
+
+    // verify: 書き込んだ内容を読み直して比較
+    let mut verifier = blake3::Hasher::new();
+    io::copy(&mut BufReader::new(File::open(dst)?), &mut verifier)?;
+    if verifier.finalize() != hasher.finalize() {
+        return Err(anyhow!("Verification failed. Path: {}", dst.to_string_ex()));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dst: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, dst)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn copy_symlink(src: &Path, dst: &Path) -> Result<()> {
+    let target = fs::read_link(src)?;
+    if src.is_dir() {
+        std::os::windows::fs::symlink_dir(target, dst)?;
+    } else {
+        std::os::windows::fs::symlink_file(target, dst)?;
+    }
+    Ok(())
+}
+
+fn preserve_meta(path: &Path, meta: &Metadata, is_dir: bool) -> Result<()> {
+    let mut times = FileTimes::new();
+    if let Ok(modified) = meta.modified() {
+        times = times.set_modified(modified);
+    }
+    if let Ok(accessed) = meta.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    // ディレクトリは OS によって開けないので、日時はできる範囲で
+    match File::open(path).and_then(|file| file.set_times(times)) {
+        Ok(()) => {}
+        Err(_) if is_dir => {}
+        Err(e) => return Err(e.into()),
+    }
+    fs::set_permissions(path, meta.permissions())?;
+    Ok(())
+}
+
+// to と同じディレクトリ (= 同じデバイス) に作り、最後に rename する
+fn temp_path(to: &Path) -> PathBuf {
+    let name = to
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    let parent = to.parent().unwrap_or(Path::new(""));
+    let mut count = 0;
+    loop {
+        let temp = parent.join(format!(".{}.a2move{}", name, count));
+        if fs::symlink_metadata(&temp).is_err() {
+            return temp;
+        }
+        count += 1;
+    }
+}
+
+fn remove_any(path: &Path) -> Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn create_tree(dir: &str) {
+        fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+        fs::write(format!("{}/a.txt", dir), b"a").unwrap();
+        fs::write(format!("{}/sub/b.bin", dir), vec![1u8; CHUNK_SIZE + 10]).unwrap();
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(format!("{}/a.txt", dir))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_move_by_copy() {
+        let test_dir = "test_move_by_copy";
+        let from = format!("{}/from", test_dir);
+        let to = format!("{}/dest/to", test_dir);
+        create_tree(&from);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::os::unix::fs::symlink("a.txt", format!("{}/link", from)).unwrap();
+            fs::set_permissions(format!("{}/a.txt", from), fs::Permissions::from_mode(0o640))
+                .unwrap();
+        }
+
+        // cancelled: nothing changes
+        let cancel = CancelToken::new();
+        let result = move_by_copy(&from, &to, &cancel, |_| cancel.cancel());
+        assert!(result.is_err());
+        assert!(Path::new(&from).exists());
+        assert_eq!(
+            fs::read_dir(format!("{}/dest", test_dir)).unwrap().count(),
+            0
+        );
+
+        // 途中で失敗しても、既存の to はそのまま
+        let old = format!("{}/dest/old.bin", test_dir);
+        fs::write(&old, b"old").unwrap();
+        let cancel = CancelToken::new();
+        let big = format!("{}/sub/b.bin", from);
+        assert!(move_by_copy(&big, &old, &cancel, |_| cancel.cancel()).is_err());
+        assert_eq!(fs::read(&old).unwrap(), b"old");
+        assert!(Path::new(&big).exists());
+        assert!(move_by_copy(&from, &old, &CancelToken::new(), |_| {}).is_err());
+        fs::remove_file(&old).unwrap();
+
+        let mut last = MoveProgress::default();
+        move_by_copy(&from, &to, &CancelToken::new(), |p| last = p.clone()).unwrap();
+        assert!(!Path::new(&from).exists());
+        assert_eq!(last.total_files, 2);
+        assert_eq!(last.copied_files, 2);
+        assert_eq!(last.copied_bytes, CHUNK_SIZE as u64 + 11);
+
+        let meta = fs::metadata(format!("{}/a.txt", to)).unwrap();
+        assert_eq!(
+            meta.modified().unwrap(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000)
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(meta.permissions().mode() & 0o777, 0o640);
+            assert_eq!(
+                fs::read_link(format!("{}/link", to)).unwrap(),
+                PathBuf::from("a.txt")
+            );
+        }
+        assert_eq!(
+            fs::read(format!("{}/sub/b.bin", to)).unwrap().len(),
+            CHUNK_SIZE + 10
+        );
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_move_by_copy_special_file() {
+        let test_dir = "test_move_by_copy_special_file";
+        let from = format!("{}/from", test_dir);
+        let to = format!("{}/to", test_dir);
+        create_tree(&from);
+        let _socket =
+            std::os::unix::net::UnixListener::bind(format!("{}/sub/socket", from)).unwrap();
+
+        // コピーを始める前に失敗し、何も変わらない
+        let result = move_by_copy(&from, &to, &CancelToken::new(), |_| {});
+        assert!(result.unwrap_err().to_string().contains("socket"));
+        assert!(Path::new(&format!("{}/a.txt", from)).exists());
+        assert!(!Path::new(&to).exists());
+        assert_eq!(fs::read_dir(test_dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    // 別デバイス (tmpfs) がある環境でのみ
+    #[test]
+    fn test_move_file_cross_device() {
+        let shm = Path::new("/dev/shm");
+        if !shm.is_dir() {
+            return;
+        }
+        let test_dir = "test_move_file_cross_device";
+        let to = shm.join("a2_test_move_file_cross_device");
+        let to = to.to_string_ex();
+        create_tree(test_dir);
+
+        if move_file_with(test_dir, &to, &MoveOptions::default(), |_| {}).is_err() {
+            // /dev/shm に書けない環境
+            fs::remove_dir_all(test_dir).unwrap();
+            return;
+        }
+        assert!(!Path::new(test_dir).exists());
+
+        // コピーが途中で止まっても上書き先は残る
+        let old = shm.join("a2_test_move_file_cross_device.old");
+        fs::write(&old, b"old").unwrap();
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let options = MoveOptions {
+            cancel,
+            ..Default::default()
+        };
+        fs::write("local_cross_device.old", b"local").unwrap();
+        assert!(move_file_with(
+            &old.to_string_ex(),
+            "local_cross_device.old",
+            &options,
+            |_| {}
+        )
+        .is_err());
+        assert_eq!(fs::read("local_cross_device.old").unwrap(), b"local");
+        fs::remove_file("local_cross_device.old").unwrap();
+        fs::remove_file(&old).unwrap();
+
+        move_file_with(&to, test_dir, &MoveOptions::default(), |_| {}).unwrap();
+        assert_eq!(fs::read(format!("{}/a.txt", test_dir)).unwrap(), b"a");
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_move_files() {
+        let test_dir = "test_move_files";
+        fs::create_dir_all(format!("{}/dest/full", test_dir)).unwrap();
+        fs::write(format!("{}/dest/full/x", test_dir), b"x").unwrap();
+        for name in ["a", "b", "c", "d"] {
+            fs::write(format!("{}/{}.txt", test_dir, name), name).unwrap();
+            fs::write(format!("{}/dest/{}.txt", test_dir, name), b"old").unwrap();
+        }
+        let item = |from: &str, to: &str| RenameItem {
+            from: PathBuf::from(format!("{}/{}", test_dir, from)),
+            to: PathBuf::from(format!("{}/{}", test_dir, to)),
+        };
+
+        let items = vec![item("a.txt", "dest/a.txt"), item("b.txt", "dest/b.txt")];
+        let options = MoveOptions {
+            conflict: ConflictPolicy::Skip,
+            ..Default::default()
+        };
+        let outcomes = move_files(&items, &options);
+        assert!(outcomes
+            .iter()
+            .all(|o| o.outcome == ConflictOutcome::Skipped));
+
+        let items = [
+            item("a.txt", "dest/a.txt"),
+            item("b.txt", "dest/b.txt"),
+            item("c.txt", "dest/new/c.txt"),
+            item("missing.txt", "dest/missing.txt"),
+            // 空でないディレクトリは上書きしない
+            item("d.txt", "dest/full"),
+        ];
+        let options = MoveOptions {
+            conflict: ConflictPolicy::KeepBoth,
+            ..Default::default()
+        };
+        let mut outcomes = move_files(&items[..2], &options);
+        let options = MoveOptions {
+            conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        outcomes.extend(move_files(&items[2..], &options));
+
+        let kept = PathBuf::from(format!("{}/dest/a (1).txt", test_dir));
+        assert_eq!(outcomes[0].outcome, ConflictOutcome::KeptBoth(kept.clone()));
+        assert_eq!(outcomes[0].to, kept);
+        assert_eq!(fs::read(&kept).unwrap(), b"a");
+        assert_eq!(
+            fs::read(format!("{}/dest/a.txt", test_dir)).unwrap(),
+            b"old"
+        );
+        assert!(matches!(outcomes[1].outcome, ConflictOutcome::KeptBoth(_)));
+        assert_eq!(outcomes[2].outcome, ConflictOutcome::Done);
+        assert!(matches!(outcomes[3].outcome, ConflictOutcome::Failed(_)));
+        assert!(matches!(outcomes[4].outcome, ConflictOutcome::Failed(_)));
+        assert!(Path::new(&format!("{}/dest/full/x", test_dir)).exists());
+
+        let outcome = move_file_with(
+            &format!("{}/d.txt", test_dir),
+            &format!("{}/dest/d.txt", test_dir),
+            &MoveOptions::default(),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(outcome, ConflictOutcome::Overwritten);
+        assert_eq!(fs::read(format!("{}/dest/d.txt", test_dir)).unwrap(), b"d");
+
+        // 種類の違うものは置き換えず、どちらも残す (fs::rename と同じ)
+        fs::create_dir_all(format!("{}/dir", test_dir)).unwrap();
+        fs::create_dir_all(format!("{}/dest/empty", test_dir)).unwrap();
+        let dest_d = format!("{}/dest/d.txt", test_dir);
+        assert!(crate::file::move_file(&format!("{}/dir", test_dir), &dest_d).is_err());
+        assert!(Path::new(&format!("{}/dir", test_dir)).is_dir());
+        assert_eq!(fs::read(&dest_d).unwrap(), b"d");
+        assert!(crate::file::move_file(&dest_d, &format!("{}/dest/empty", test_dir)).is_err());
+        assert!(Path::new(&format!("{}/dest/empty", test_dir)).is_dir());
+        assert_eq!(fs::read(&dest_d).unwrap(), b"d");
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::file::{ConflictOutcome, ConflictPolicy, FileInfo, OptionPathUtil, PathUtil};
+use crate::time::Timestamp;
+
+const INFO_EXTENSION: &str = ".trashinfo";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrashItem {
+    /// The entry in the trash (`files/<name>`)
+    pub info: FileInfo,
+    /// Name in the trash, shared by `files/` and `info/`
+    pub name: String,
+    pub original_path: PathBuf,
+    /// DeletionDate of the .trashinfo (YYYY-MM-DDThh:mm:ss, local time as the spec requires)
+    pub deleted_at: String,
+}
+
+/// Trash dir of the freedesktop.org Trash spec (`files/` and `info/*.trashinfo`)
+///
+/// Only the trash on the same device is supported (no `$topdir/.Trash-$uid`).
+/// Trashing a file on another device fails.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    pub dir: PathBuf,
+}
+
+impl Trash {
+    /// `$XDG_DATA_HOME/Trash` (default: `~/.local/share/Trash`)
+    pub fn home() -> Result<Trash> {
+        let data_home = match std::env::var_os("XDG_DATA_HOME").filter(|v| !v.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let home = std::env::var_os("HOME").ok_or_else(|| anyhow!("HOME is not set"))?;
+                PathBuf::from(home).join(".local/share")
+            }
+        };
+        Ok(Trash::new(data_home.join("Trash")))
+    }
+
+    pub fn new(dir: impl Into<PathBuf>) -> Trash {
+        Trash { dir: dir.into() }
+    }
+
+    fn files_dir(&self) -> PathBuf {
+        self.dir.join("files")
+    }
+
+    fn info_dir(&self) -> PathBuf {
+        self.dir.join("info")
+    }
+
+    /// Move `path` into the trash. `path` must be on the same device as the trash dir.
+    pub fn trash(&self, path: &str) -> Result<TrashItem> {
+        let original_path = absolute(Path::new(path))?;
+        if fs::symlink_metadata(&original_path).is_err() {
+            return Err(anyhow!("Not found. Path: {}", path));
+        }
+        fs::create_dir_all(self.files_dir())?;
+        fs::create_dir_all(self.info_dir())?;
+
+        let deleted_at = Timestamp::to_local_string(Timestamp::from_system_time(SystemTime::now()));
+        let info = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(&original_path),
+            deleted_at
+        );
+
+        // spec: .trashinfo を create_new で作って名前を確保してから移動する
+        let file_name = original_path.file_name().to_string_ex();
+        let mut count = 1;
+        let name = loop {
+            let name = numbered_name(&file_name, count);
+            let info_path = self.info_path(&name);
+            if !self.files_dir().join(&name).exists() {
+                match fs::File::options()
+                    .write(true)
+                    .create_new(true)
+                    .open(&info_path)
+                {
+                    Ok(mut file) => {
+                        file.write_all(info.as_bytes())?;
+                        break name;
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            count += 1;
+        };
+
+        let trashed = self.files_dir().join(&name);
+        if let Err(e) = fs::rename(&original_path, &trashed) {
+            let _ = fs::remove_file(self.info_path(&name));
+            return Err(anyhow!("Failed to trash. Path: {}, {}", path, e));
+        }
+
+        Ok(TrashItem {
+            info: FileInfo::from_path(&trashed).load_meta(),
+            name,
+            original_path,
+            deleted_at,
+        })
+    }
+
+    /// Items in the trash. Broken .trashinfo files are skipped.
+    pub fn list(&self) -> Result<Vec<TrashItem>> {
+        let entries = match fs::read_dir(self.info_dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut items: Vec<TrashItem> = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let Some(name) = file_name.strip_suffix(INFO_EXTENSION) else {
+                continue;
+            };
+            let trashed = self.files_dir().join(name);
+            if fs::symlink_metadata(&trashed).is_err() {
+                continue;
+            }
+            let Some((original_path, deleted_at)) = fs::read_to_string(entry.path())
+                .ok()
+                .and_then(|text| parse_info(&text))
+            else {
+                continue;
+            };
+            items.push(TrashItem {
+                info: FileInfo::from_path(&trashed).load_meta(),
+                name: name.to_string(),
+                original_path,
+                deleted_at,
+            });
+        }
+        items.sort_by(|a, b| a.deleted_at.cmp(&b.deleted_at).then(a.name.cmp(&b.name)));
+        Ok(items)
+    }
+
+    /// Move the item back to the original path. Fails if something exists there.
+    pub fn restore(&self, item: &TrashItem) -> Result<()> {
+        self.restore_with(item, ConflictPolicy::Fail)?;
+        Ok(())
+    }
+
+    /// `restore` with a policy for an existing original path. A skipped item stays in the trash.
+    pub fn restore_with(
+        &self,
+        item: &TrashItem,
+        policy: ConflictPolicy,
+    ) -> Result<ConflictOutcome> {
+        let trashed = self.files_dir().join(&item.name);
+        let source = fs::symlink_metadata(&trashed)?;
+        let (to, outcome) = policy.resolve(&item.original_path, source.modified().ok())?;
+        let Some(to) = to else {
+            return Ok(outcome);
+        };
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&trashed, &to)
+            .with_context(|| format!("Failed to restore. Path: {}", to.to_string_ex()))?;
+        fs::remove_file(self.info_path(&item.name))?;
+        Ok(outcome)
+    }
+
+    /// Delete the item permanently
+    pub fn erase(&self, item: &TrashItem) -> Result<()> {
+        remove_any(&self.files_dir().join(&item.name))?;
+        fs::remove_file(self.info_path(&item.name))?;
+        Ok(())
+    }
+
+    /// Delete everything in the trash permanently
+    pub fn empty(&self) -> Result<()> {
+        for dir in [self.files_dir(), self.info_dir()] {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for entry in entries {
+                remove_any(&entry?.path())?;
+            }
+        }
+        // ゴミ箱のサイズのキャッシュ (spec 1.0)
+        let _ = fs::remove_file(self.dir.join("directorysizes"));
+        Ok(())
+    }
+
+    fn info_path(&self, name: &str) -> PathBuf {
+        self.info_dir().join(format!("{}{}", name, INFO_EXTENSION))
+    }
+}
+
+/// Move `path` into the trash of the user (`Trash::home()`). Same device only.
+pub fn trash(path: &str) -> Result<TrashItem> {
+    Trash::home()?.trash(path)
+}
+
+fn remove_any(path: &Path) -> Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+// symlink 自体を捨てるため、最後の要素は解決しない
+fn absolute(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid path. Path: {}", path.to_string_ex()))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    Ok(fs::canonicalize(parent)?.join(file_name))
+}
+
+// "a.txt" -> "a.txt", "a.2.txt", "a.3.txt" ...
+fn numbered_name(file_name: &str, count: usize) -> String {
+    if count == 1 {
+        return file_name.to_string();
+    }
+    match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{}.{}.{}", stem, count, ext),
+        _ => format!("{}.{}", file_name, count),
+    }
+}
+
+fn parse_info(text: &str) -> Option<(PathBuf, String)> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next()? != "[Trash Info]" {
+        return None;
+    }
+    let mut path: Option<PathBuf> = None;
+    let mut date = String::new();
+    for line in lines {
+        if line.starts_with('[') {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Path=") {
+            path = Some(PathBuf::from(decode_path(value)?));
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            date = value.to_string();
+        }
+    }
+    Some((path?, date))
+}
+
+// Path= は URL と同じ % エンコード ("/" はそのまま)
+fn encode_path(path: &Path) -> String {
+    let mut out = String::new();
+    for b in path.to_string_ex().bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                out.push(b as char)
+            }
+            b => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+fn decode_path(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut out: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trash() {
+        let test_dir = "test_trash";
+        let trash = Trash::new("test_trash_store");
+        fs::create_dir_all(format!("{}/sub", test_dir)).unwrap();
+        fs::write(format!("{}/a b%.txt", test_dir), b"a").unwrap();
+        fs::write(format!("{}/sub/a b%.txt", test_dir), b"a2").unwrap();
+
+        let before = Timestamp::from_system_time(SystemTime::now());
+        let item = trash.trash(&format!("{}/a b%.txt", test_dir)).unwrap();
+        assert_eq!(item.name, "a b%.txt");
+        // DeletionDate は local time
+        let after = Timestamp::from_system_time(SystemTime::now());
+        assert!((before..=after).any(|t| Timestamp::to_local_string(t) == item.deleted_at));
+        let second = trash.trash(&format!("{}/sub/a b%.txt", test_dir)).unwrap();
+        assert_eq!(second.name, "a b%.2.txt");
+        let dir_item = trash.trash(&format!("{}/sub", test_dir)).unwrap();
+        assert!(dir_item.info.is_dir);
+
+        let info = fs::read_to_string(trash.info_path(&item.name)).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath=/"));
+        assert!(info.contains("a%20b%25.txt"));
+
+        let items = trash.list().unwrap();
+        assert_eq!(items.len(), 3);
+        let listed = items.iter().find(|i| i.name == item.name).unwrap();
+        assert_eq!(listed.original_path, item.original_path);
+        assert!(!crate::file::is_exists(&format!("{}/a b%.txt", test_dir)));
+
+        trash.restore(listed).unwrap();
+        assert_eq!(fs::read(format!("{}/a b%.txt", test_dir)).unwrap(), b"a");
+        assert_eq!(trash.list().unwrap().len(), 2);
+
+        // the original path is used again
+        let item = trash.trash(&format!("{}/a b%.txt", test_dir)).unwrap();
+        fs::write(format!("{}/a b%.txt", test_dir), b"new").unwrap();
+        assert!(trash.restore(&item).is_err());
+        let outcome = trash.restore_with(&item, ConflictPolicy::Skip).unwrap();
+        assert_eq!(outcome, ConflictOutcome::Skipped);
+        let outcome = trash.restore_with(&item, ConflictPolicy::KeepBoth).unwrap();
+        let kept = item.original_path.with_file_name("a b% (1).txt");
+        assert_eq!(fs::read(&kept).unwrap(), b"a");
+        assert_eq!(outcome, ConflictOutcome::KeptBoth(kept));
+        assert_eq!(trash.list().unwrap().len(), 2);
+
+        trash.empty().unwrap();
+        assert!(trash.list().unwrap().is_empty());
+
+        fs::remove_dir_all(test_dir).unwrap();
+        fs::remove_dir_all(&trash.dir).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::file::zip_util::ZipUtil;
+use crate::file::{walk_with, FileEntry, FileInfo, PathUtil, WalkOptions};
+
+/// Build a tree from a walk of `dir`. `f` computes `T` of each node (including the root).
+/// Directories excluded by `WalkOptions.query` are still nodes when they contain matched entries.
+pub fn build_tree<T>(
+    dir: &str,
+    options: WalkOptions,
+    mut f: impl FnMut(&FileInfo) -> T,
+) -> Result<FileEntry<T>> {
+    let root_path = PathBuf::from(dir);
+    let mut children: HashMap<PathBuf, Vec<FileInfo>> = HashMap::new();
+    let mut dirs: HashSet<PathBuf> = HashSet::new();
+    for entry in walk_with(dir, options) {
+        let entry = entry?;
+        add_missing_dirs(&root_path, &entry.parent, &mut children, &mut dirs);
+        if entry.info.is_dir {
+            dirs.insert(entry.info.path.clone());
+        }
+        children.entry(entry.parent).or_default().push(entry.info);
+    }
+
+    let mut root = FileInfo::from_path(Path::new(dir));
+    root.path = root_path;
+    root.set_type(true, false);
+
+    Ok(assemble(root, &mut children, &mut f))
+}
+
+// query で除外されたディレクトリの中身が木から落ちないよう、親のディレクトリを補う
+fn add_missing_dirs(
+    root: &Path,
+    parent: &Path,
+    children: &mut HashMap<PathBuf, Vec<FileInfo>>,
+    dirs: &mut HashSet<PathBuf>,
+) {
+    let mut current = parent;
+    while current != root && !dirs.contains(current) {
+        let Some(up) = current.parent() else {
+            break;
+        };
+        dirs.insert(current.to_path_buf());
+        let mut info = FileInfo::from_path(current);
+        info.set_type(true, false);
+        children.entry(up.to_path_buf()).or_default().push(info);
+        current = up;
+    }
+}
+
+/// Build a tree from a zip listing. The root is the zip file itself.
+pub fn build_zip_tree<T>(path: &str, mut f: impl FnMut(&FileInfo) -> T) -> Result<FileEntry<T>> {
+    let (_, infos) = ZipUtil::read_file_infos(path)?;
+
+    let mut children: HashMap<PathBuf, Vec<FileInfo>> = HashMap::new();
+    for info in infos {
+        children.entry(info.dir.clone()).or_default().push(info);
+    }
+
+    let root = FileInfo::from_path(Path::new(&path.remove_ends_separator()));
+    Ok(assemble(root, &mut children, &mut f))
+}
+
+fn assemble<T>(
+    info: FileInfo,
+    children: &mut HashMap<PathBuf, Vec<FileInfo>>,
+    f: &mut impl FnMut(&FileInfo) -> T,
+) -> FileEntry<T> {
+    let meta = f(&info);
+    let infos = children.remove(&info.path).unwrap_or_default();
+    FileEntry {
+        children: infos
+            .into_iter()
+            .map(|child| assemble(child, children, f))
+            .collect(),
+        info,
+        meta,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::{FileQuery, QueryKind, SortBy, SortKey};
+
+    #[test]
+    fn test_build_tree() {
+        let test_dir = "test_build_tree";
+        std::fs::create_dir_all(format!("{}/sub", test_dir)).unwrap();
+        std::fs::write(format!("{}/sub/10.jpg", test_dir), b"test").unwrap();
+        std::fs::write(format!("{}/sub/9.jpg", test_dir), b"test").unwrap();
+        std::fs::write(format!("{}/file.txt", test_dir), b"test").unwrap();
+
+        let mut tree = build_tree(test_dir, WalkOptions::default(), |info| info.is_image).unwrap();
+        assert_eq!(tree.children.len(), 2);
+        assert_eq!(tree.flatten().len(), 5);
+
+        let sub = PathBuf::from(test_dir).join("sub");
+        tree.sort_children(&[SortBy::asc(SortKey::Name)]);
+        let names: Vec<&str> = tree
+            .find(&sub)
+            .unwrap()
+            .children
+            .iter()
+            .map(|child| child.info.file_name.as_str())
+            .collect();
+        assert_eq!(names, vec!["9.jpg", "10.jpg"]);
+
+        // keep only dirs and images
+        tree.prune(&|entry| entry.info.is_dir || entry.meta);
+        let tree = tree.map(&mut |info, _| info.file_name.clone());
+        let names: Vec<&str> = tree.flatten().iter().map(|e| e.meta.as_str()).collect();
+        assert_eq!(names, vec![test_dir, "sub", "9.jpg", "10.jpg"]);
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_build_tree_query() {
+        let test_dir = "test_build_tree_query";
+        std::fs::create_dir_all(format!("{}/sub/deep", test_dir)).unwrap();
+        std::fs::create_dir_all(format!("{}/no_image", test_dir)).unwrap();
+        std::fs::write(format!("{}/sub/deep/1.jpg", test_dir), b"test").unwrap();
+        std::fs::write(format!("{}/no_image/a.txt", test_dir), b"test").unwrap();
+
+        // dirs do not match the query, but the ones with images are kept
+        let options = WalkOptions {
+            query: Some(FileQuery::new().kind(QueryKind::Image)),
+            ..Default::default()
+        };
+        let tree = build_tree(test_dir, options, |_| ()).unwrap();
+        let deep = PathBuf::from(test_dir).join("sub").join("deep");
+        let node = tree.find(&deep).unwrap();
+        assert!(node.info.is_dir && node.info.kind == crate::file::FileKind::Dir);
+        assert_eq!(node.children[0].info.file_name, "1.jpg");
+        assert_eq!(tree.children.len(), 1);
+        assert_eq!(tree.flatten().len(), 4);
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_build_zip_tree() {
+        let tree = build_zip_tree("tests/data/sample.zip", |_| ()).unwrap();
+        assert!(tree.info.is_zip);
+        assert_eq!(tree.children.len(), 1);
+
+        let dir1 = Path::new("tests/data/sample.zip/sample/dir1");
+        assert_eq!(tree.find(dir1).unwrap().children.len(), 2);
+    }
+}
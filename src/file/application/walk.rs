@@ -0,0 +1,462 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::file::application::ignore::IgnoreLevel;
+use crate::file::{
+    read_dir_lenient, read_dir_with, FileInfo, FileQuery, IgnoreOptions, PathUtil, ReadDirOptions,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalkOrder {
+    /// 見つけたディレクトリの中身を、兄弟より先に返す
+    #[default]
+    DepthFirst,
+    /// 同じ階層をすべて返してから、次の階層へ進む
+    BreadthFirst,
+    /// ディレクトリの中身をすべて返してから、子ディレクトリへ順に深く進む (`read_dir_deep` の順)
+    ListingFirst,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Number of levels to read. `Some(1)` is same as `read_dir`, `None` is unlimited.
+    pub max_depth: Option<usize>,
+    pub order: WalkOrder,
+    /// Only matched entries are yielded. Directories pruned by the query are not read.
+    pub query: Option<FileQuery>,
+    /// Skip entries matched by gitignore style rules. Ignored directories are not read.
+    /// None (default): nothing is ignored, `.gitignore` files are not read either.
+    pub ignore: Option<IgnoreOptions>,
+    /// Follow symlinks to directories. Links to a directory being walked (its ancestors or itself) are not followed,
+    /// so cycles are never followed. Other directories can be read several times through different links.
+    pub follow_links: bool,
+    /// Detect formats of files from their contents (IO cost). See `FileInfo::sniff`
+    pub sniff: bool,
+    /// Fill `FileInfo.meta` while reading directories. See `ReadDirOptions`
+    pub with_meta: bool,
+    /// Skip directories and entries which can not be read (permission denied, etc...) and keep them in
+    /// `Walk::take_failures` (`walk_parallel`: the returned failures), instead of yielding errors.
+    /// The root directory still fails.
+    pub lenient: bool,
+}
+
+/// Directory or entry skipped by `WalkOptions.lenient`, with the reason
+pub type WalkFailure = (PathBuf, anyhow::Error);
+
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    pub info: FileInfo,
+    /// 0 for direct children of the walk root
+    pub depth: usize,
+    pub parent: PathBuf,
+}
+
+/// Lazy directory walker.
+/// Directories are read one at a time when the iterator reaches them, so callers can stop early.
+pub struct Walk {
+    options: WalkOptions,
+    // directories waiting to be read
+    pending: VecDeque<Pending>,
+    // listings being yielded (DepthFirst: stack, BreadthFirst: only one)
+    levels: Vec<Level>,
+    // ListingFirst: directories found in the current listing
+    deferred: Vec<Pending>,
+    failures: Vec<WalkFailure>,
+}
+
+pub fn walk(dir: &str) -> Walk {
+    walk_with(dir, WalkOptions::default())
+}
+
+pub fn walk_with(dir: &str, options: WalkOptions) -> Walk {
+    Walk::new(dir, options)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum FileId {
+    #[cfg(unix)]
+    Inode(u64, u64),
+    #[cfg(not(unix))]
+    Path(PathBuf),
+}
+
+#[cfg(unix)]
+fn file_id(path: &Path) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = std::fs::metadata(path).ok()?;
+    Some(FileId::Inode(meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(path: &Path) -> Option<FileId> {
+    std::fs::canonicalize(path).ok().map(FileId::Path)
+}
+
+/// Directory waiting to be read
+pub(crate) struct Pending {
+    pub(crate) dir: PathBuf,
+    pub(crate) depth: usize,
+    ignore: Option<Arc<IgnoreLevel>>,
+    // follow_links: ids of the dir and its ancestors (device + inode), to detect cycles
+    ancestors: Vec<FileId>,
+}
+
+/// Listing of a directory
+pub(crate) struct Level {
+    infos: std::vec::IntoIter<FileInfo>,
+    pub(crate) failures: Vec<WalkFailure>,
+    depth: usize,
+    dir: PathBuf,
+    ignore: Option<Arc<IgnoreLevel>>,
+    ancestors: Vec<FileId>,
+}
+
+impl Pending {
+    pub(crate) fn root(dir: &str, options: &WalkOptions) -> Option<Pending> {
+        if options.max_depth == Some(0) {
+            return None;
+        }
+        let dir = PathBuf::from(dir);
+        let ancestors = match options.follow_links {
+            true => file_id(&dir).into_iter().collect(),
+            false => Vec::new(),
+        };
+        let ignore = options
+            .ignore
+            .as_ref()
+            .and_then(|ignore| IgnoreLevel::root(&dir, ignore));
+        Some(Pending {
+            dir,
+            depth: 0,
+            ignore,
+            ancestors,
+        })
+    }
+
+    /// Read the directory (IO)
+    pub(crate) fn read(self, options: &WalkOptions) -> Result<Level> {
+        let read_options = ReadDirOptions {
+            with_meta: options.with_meta,
+        };
+        let (infos, failures) = if options.lenient {
+            let report = read_dir_lenient(&self.dir.to_string_ex(), &read_options)?;
+            (report.infos, report.failures)
+        } else {
+            let infos = read_dir_with(&self.dir.to_string_ex(), &read_options)?;
+            (infos, Vec::new())
+        };
+        let ignore = match &options.ignore {
+            Some(ignore) => IgnoreLevel::load(&self.dir, ignore, self.ignore),
+            None => self.ignore,
+        };
+        Ok(Level {
+            infos: infos.into_iter(),
+            failures,
+            depth: self.depth,
+            dir: self.dir,
+            ignore,
+            ancestors: self.ancestors,
+        })
+    }
+}
+
+impl Level {
+    /// Apply options to an entry of this listing.
+    /// Returns the entry to yield and the directory to read next.
+    pub(crate) fn visit(
+        &self,
+        options: &WalkOptions,
+        mut info: FileInfo,
+    ) -> (Option<WalkEntry>, Option<Pending>) {
+        if options.follow_links && info.is_symlink && !info.is_broken_link {
+            // resolve the target type
+            if let Ok(meta) = std::fs::metadata(&info.path) {
+                info.set_type(meta.is_dir(), meta.is_file());
+            }
+        }
+
+        if let Some(ignore) = &self.ignore {
+            if ignore.is_ignored(&info.path, info.is_dir) {
+                return (None, None);
+            }
+        }
+
+        let can_descend = match options.max_depth {
+            Some(max_depth) => self.depth + 1 < max_depth,
+            None => true,
+        };
+        let query_descend = match &options.query {
+            Some(query) => query.should_descend(&info),
+            None => true,
+        };
+        let descend = info.is_dir && can_descend && query_descend;
+        let pending = match descend {
+            true => self.child(options, &info.path),
+            false => None,
+        };
+
+        if options.sniff && info.is_file {
+            info.sniff();
+        }
+
+        if let Some(query) = &options.query {
+            if !query.matches(&mut info) {
+                return (None, pending);
+            }
+        }
+
+        let entry = WalkEntry {
+            info,
+            depth: self.depth,
+            parent: self.dir.clone(),
+        };
+        (Some(entry), pending)
+    }
+
+    // follow_links: 祖先 (自分を含む) へのリンクは循環なので読まない。識別できないものも読まない
+    fn child(&self, options: &WalkOptions, dir: &Path) -> Option<Pending> {
+        let ancestors = match options.follow_links {
+            true => {
+                let id = file_id(dir)?;
+                if self.ancestors.contains(&id) {
+                    return None;
+                }
+                let mut ancestors = self.ancestors.clone();
+                ancestors.push(id);
+                ancestors
+            }
+            false => Vec::new(),
+        };
+        Some(Pending {
+            dir: dir.to_path_buf(),
+            depth: self.depth + 1,
+            ignore: self.ignore.clone(),
+            ancestors,
+        })
+    }
+}
+
+impl Iterator for Level {
+    type Item = FileInfo;
+
+    fn next(&mut self) -> Option<FileInfo> {
+        self.infos.next()
+    }
+}
+
+impl Walk {
+    pub fn new(dir: &str, options: WalkOptions) -> Self {
+        let pending = Pending::root(dir, &options).into_iter().collect();
+        Walk {
+            options,
+            pending,
+            levels: Vec::new(),
+            deferred: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    /// Directories and entries skipped by `WalkOptions.lenient` so far
+    pub fn take_failures(&mut self) -> Vec<WalkFailure> {
+        std::mem::take(&mut self.failures)
+    }
+
+    fn next_pending(&mut self) -> Option<Pending> {
+        match self.options.order {
+            // 直前に返したディレクトリを先に読む
+            WalkOrder::DepthFirst => self.pending.pop_back(),
+            // 今の階層を返し終えるまで読まない
+            WalkOrder::BreadthFirst if self.levels.is_empty() => self.pending.pop_front(),
+            WalkOrder::BreadthFirst => None,
+            // 今の中身を返し終えたら、見つけた順に
+            WalkOrder::ListingFirst if self.levels.is_empty() => self.pending.pop_back(),
+            WalkOrder::ListingFirst => None,
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<WalkEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(pending) = self.next_pending() {
+                let (dir, depth) = (pending.dir.clone(), pending.depth);
+                match pending.read(&self.options) {
+                    Ok(mut level) => {
+                        self.failures.append(&mut level.failures);
+                        self.levels.push(level);
+                    }
+                    Err(e) if self.options.lenient && depth > 0 => self.failures.push((dir, e)),
+                    Err(e) => return Some(Err(e)),
+                }
+                continue;
+            }
+
+            let level = self.levels.last_mut()?;
+            let Some(info) = level.next() else {
+                self.levels.pop();
+                // 子ディレクトリを兄弟 (先に見つけた残り) より先に読む
+                self.pending.extend(self.deferred.drain(..).rev());
+                continue;
+            };
+
+            let (entry, pending) = level.visit(&self.options, info);
+            if let Some(pending) = pending {
+                match self.options.order {
+                    WalkOrder::ListingFirst => self.deferred.push(pending),
+                    _ => self.pending.push_back(pending),
+                }
+            }
+            if let Some(entry) = entry {
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_tree(test_dir: &str) {
+        std::fs::create_dir_all(format!("{}/a/aa", test_dir)).unwrap();
+        std::fs::create_dir_all(format!("{}/b", test_dir)).unwrap();
+        std::fs::write(format!("{}/a/aa/file.txt", test_dir), b"test").unwrap();
+        std::fs::write(format!("{}/b/file.txt", test_dir), b"test").unwrap();
+    }
+
+    #[test]
+    fn test_walk_depth_first() {
+        let test_dir = "test_walk_depth_first";
+        create_tree(test_dir);
+
+        let entries: Vec<WalkEntry> = walk(test_dir).map(|e| e.unwrap()).collect();
+        assert_eq!(entries.len(), 5);
+
+        // children come right after their directory
+        let a = entries
+            .iter()
+            .position(|e| e.info.file_name == "a")
+            .unwrap();
+        assert_eq!(entries[a + 1].info.file_name, "aa");
+        assert_eq!(entries[a + 1].depth, 1);
+        assert_eq!(entries[a + 2].depth, 2);
+        assert_eq!(
+            entries[a + 2].parent,
+            PathBuf::from(test_dir).join("a").join("aa")
+        );
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_walk_breadth_first_max_depth() {
+        let test_dir = "test_walk_breadth_first_max_depth";
+        create_tree(test_dir);
+
+        let options = WalkOptions {
+            max_depth: Some(2),
+            order: WalkOrder::BreadthFirst,
+            ..Default::default()
+        };
+        let depths: Vec<usize> = walk_with(test_dir, options)
+            .map(|e| e.unwrap().depth)
+            .collect();
+        assert_eq!(depths, vec![0, 0, 1, 1]);
+
+        // stop early without reading the rest
+        let first = walk(test_dir).next().unwrap().unwrap();
+        assert_eq!(first.depth, 0);
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_walk_listing_first() {
+        let test_dir = "test_walk_listing_first";
+        create_tree(test_dir);
+        std::fs::create_dir_all(format!("{}/a/ab", test_dir)).unwrap();
+        std::fs::write(format!("{}/c.txt", test_dir), b"test").unwrap();
+
+        let options = WalkOptions {
+            order: WalkOrder::ListingFirst,
+            ..Default::default()
+        };
+        let entries: Vec<WalkEntry> = walk_with(test_dir, options).map(|e| e.unwrap()).collect();
+        assert_eq!(entries.len(), 7);
+        // the whole listing of a directory comes together
+        let parents: Vec<&PathBuf> = entries.iter().map(|e| &e.parent).collect();
+        let mut groups = parents.clone();
+        groups.dedup();
+        assert_eq!(groups.len(), 4);
+        assert!(entries[..3].iter().all(|e| e.depth == 0));
+        // a の中身の次は、b より先に a/aa
+        let a = PathBuf::from(test_dir).join("a");
+        let after_a = parents.iter().rposition(|p| **p == a).unwrap() + 1;
+        assert_eq!(entries[after_a].depth, 2);
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_follow_links() {
+        let test_dir = "test_walk_follow_links";
+        create_tree(test_dir);
+        let base = std::fs::canonicalize(test_dir).unwrap();
+        std::os::unix::fs::symlink(base.join("b"), base.join("a/link_b")).unwrap();
+        std::os::unix::fs::symlink(&base, base.join("a/aa/loop")).unwrap();
+        std::os::unix::fs::symlink(base.join("none"), base.join("broken")).unwrap();
+
+        let entries: Vec<WalkEntry> = walk(test_dir).map(|e| e.unwrap()).collect();
+        assert_eq!(entries.len(), 8);
+        let broken = entries
+            .iter()
+            .find(|e| e.info.file_name == "broken")
+            .unwrap();
+        assert!(broken.info.is_broken_link);
+        assert_eq!(broken.info.link_target, Some(base.join("none")));
+
+        std::os::unix::fs::symlink(base.join("b"), base.join("a/aa/link_b2")).unwrap();
+
+        // symlink to an image
+        std::fs::write(base.join("x.jpg"), b"jpg").unwrap();
+        std::os::unix::fs::symlink(base.join("x.jpg"), base.join("pic.jpg")).unwrap();
+
+        let options = WalkOptions {
+            follow_links: true,
+            ..Default::default()
+        };
+        let entries: Vec<WalkEntry> = walk_with(test_dir, options).map(|e| e.unwrap()).collect();
+        // every link to b is followed (not a cycle), loop to the root is never followed
+        let files = entries
+            .iter()
+            .filter(|e| e.info.file_name == "file.txt")
+            .count();
+        assert_eq!(files, 4);
+        let link_b = entries
+            .iter()
+            .find(|e| e.info.file_name == "link_b")
+            .unwrap();
+        assert!(link_b.info.is_dir && link_b.info.is_symlink);
+        assert_eq!(link_b.info.kind, crate::file::FileKind::Dir);
+        let pic = entries
+            .iter()
+            .find(|e| e.info.file_name == "pic.jpg")
+            .unwrap();
+        assert!(pic.info.is_image && pic.info.kind == crate::file::FileKind::Image);
+
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_walk_no_target() {
+        let mut walk = walk("notargetdir_walk");
+        assert!(walk.next().unwrap().is_err());
+        assert!(walk.next().is_none());
+    }
+}
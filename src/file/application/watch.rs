@@ -0,0 +1,268 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::file::FileInfo;
+
+// Renamed だけ大きいが、イベントはすぐ消費されるので Box にしない
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum FileEvent {
+    Created(FileInfo),
+    Modified(FileInfo),
+    /// Meta is not loaded (the file does not exist anymore)
+    Removed(FileInfo),
+    Renamed {
+        from: FileInfo,
+        to: FileInfo,
+    },
+}
+
+impl FileEvent {
+    /// Current path of the entry
+    pub fn path(&self) -> &Path {
+        match self {
+            FileEvent::Created(info) | FileEvent::Modified(info) | FileEvent::Removed(info) => {
+                &info.path
+            }
+            FileEvent::Renamed { to, .. } => &to.path,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    pub recursive: bool,
+    /// Events are sent after no event arrived for this duration
+    pub debounce: Duration,
+    /// Upper limit of waiting while events keep arriving
+    pub max_delay: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            recursive: true,
+            debounce: Duration::from_millis(200),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Watching continues while this is alive.
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+/// Watch `dir` and call `f` with debounced and coalesced events.
+/// `f` is called on a background thread.
+pub fn watch(
+    dir: &str,
+    options: WatchOptions,
+    mut f: impl FnMut(FileEvent) + Send + 'static,
+) -> Result<FileWatcher> {
+    let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let mode = if options.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    watcher
+        .watch(Path::new(dir), mode)
+        .with_context(|| format!("Failed to watch. Path: {}", dir))?;
+
+    // watcher が drop されると tx も drop され、recv がエラーになってスレッドが終わる
+    std::thread::spawn(move || {
+        while let Ok(first) = rx.recv() {
+            let mut events: Vec<Event> = first.into_iter().collect();
+            let started = Instant::now();
+            loop {
+                let remaining = options.max_delay.saturating_sub(started.elapsed());
+                match rx.recv_timeout(options.debounce.min(remaining)) {
+                    Ok(event) => events.extend(event),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            for event in coalesce(events) {
+                f(event);
+            }
+        }
+    });
+
+    Ok(FileWatcher { _watcher: watcher })
+}
+
+// path ごとにまとめた状態
+#[derive(Debug, Clone)]
+enum Change {
+    Created,
+    Modified,
+    Removed,
+    RenamedFrom(PathBuf),
+}
+
+#[derive(Default)]
+struct Changes {
+    // 発生順を保つため順序は Vec で持つ
+    order: Vec<PathBuf>,
+    changes: HashMap<PathBuf, Change>,
+}
+
+impl Changes {
+    fn set(&mut self, path: PathBuf, change: Change) {
+        if !self.changes.contains_key(&path) {
+            self.order.push(path.clone());
+        }
+        self.changes.insert(path, change);
+    }
+
+    fn take(&mut self, path: &Path) -> Option<Change> {
+        self.changes.remove(path)
+    }
+
+    fn create(&mut self, path: PathBuf) {
+        match self.changes.get(&path) {
+            None => self.set(path, Change::Created),
+            // 消えて作り直された = 更新
+            Some(Change::Removed) => self.set(path, Change::Modified),
+            Some(_) => {}
+        }
+    }
+
+    fn modify(&mut self, path: PathBuf) {
+        if !self.changes.contains_key(&path) {
+            self.set(path, Change::Modified);
+        }
+    }
+
+    fn remove(&mut self, path: PathBuf) {
+        match self.take(&path) {
+            // 作られてすぐ消えたものは通知しない
+            Some(Change::Created) => {}
+            Some(Change::RenamedFrom(from)) => self.set(from, Change::Removed),
+            _ => self.set(path, Change::Removed),
+        }
+    }
+
+    fn rename(&mut self, from: PathBuf, to: PathBuf) {
+        let change = match self.take(&from) {
+            Some(Change::Created) => Change::Created,
+            Some(Change::RenamedFrom(origin)) => Change::RenamedFrom(origin),
+            _ => Change::RenamedFrom(from),
+        };
+        match change {
+            Change::RenamedFrom(origin) if origin == to => self.set(to, Change::Modified),
+            change => self.set(to, change),
+        }
+    }
+
+    fn into_events(self) -> Vec<FileEvent> {
+        let mut changes = self.changes;
+        self.order
+            .into_iter()
+            .filter_map(|path| {
+                let change = changes.remove(&path)?;
+                let event = match change {
+                    Change::Created => FileEvent::Created(FileInfo::from_path(&path).load_meta()),
+                    Change::Modified => FileEvent::Modified(FileInfo::from_path(&path).load_meta()),
+                    Change::Removed => FileEvent::Removed(FileInfo::from_path(&path)),
+                    Change::RenamedFrom(from) => FileEvent::Renamed {
+                        from: FileInfo::from_path(&from),
+                        to: FileInfo::from_path(&path).load_meta(),
+                    },
+                };
+                Some(event)
+            })
+            .collect()
+    }
+}
+
+// 連続したイベントを path ごとにまとめる
+fn coalesce(events: Vec<Event>) -> Vec<FileEvent> {
+    // inotify は rename を From/To と、対応付けた Both の両方で通知するので、Both がある分は From/To を無視する
+    let paired: HashSet<usize> = events
+        .iter()
+        .filter(|event| event.kind == EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+        .filter_map(|event| event.tracker())
+        .collect();
+    let is_paired = |event: &Event| event.tracker().is_some_and(|t| paired.contains(&t));
+
+    let mut changes = Changes::default();
+    for mut event in events {
+        match event.kind {
+            EventKind::Access(_) => {}
+            EventKind::Create(_) => event.paths.into_iter().for_each(|p| changes.create(p)),
+            EventKind::Remove(_) => event.paths.into_iter().for_each(|p| changes.remove(p)),
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                let to = event.paths.pop().unwrap();
+                let from = event.paths.pop().unwrap();
+                changes.rename(from, to);
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) if !is_paired(&event) => {
+                event.paths.into_iter().for_each(|p| changes.remove(p))
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) if !is_paired(&event) => {
+                event.paths.into_iter().for_each(|p| changes.create(p))
+            }
+            EventKind::Modify(ModifyKind::Name(_)) if is_paired(&event) => {}
+            _ => event.paths.into_iter().for_each(|p| changes.modify(p)),
+        }
+    }
+    changes.into_events()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recv(rx: &mpsc::Receiver<FileEvent>) -> FileEvent {
+        rx.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn test_watch() {
+        let test_dir = "test_watch";
+        std::fs::create_dir_all(test_dir).unwrap();
+        let options = WatchOptions {
+            debounce: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let (tx, rx) = mpsc::channel();
+        let watcher = watch(test_dir, options, move |event| {
+            let _ = tx.send(event);
+        })
+        .unwrap();
+
+        // create + write are coalesced into one event
+        std::fs::write(format!("{}/a.txt", test_dir), b"a").unwrap();
+        let event = recv(&rx);
+        assert!(
+            matches!(&event, FileEvent::Created(info) if info.file_name == "a.txt" && info.meta.is_some())
+        );
+
+        std::fs::rename(format!("{}/a.txt", test_dir), format!("{}/b.txt", test_dir)).unwrap();
+        let event = recv(&rx);
+        assert!(
+            matches!(&event, FileEvent::Renamed { from, to } if from.file_name == "a.txt" && to.file_name == "b.txt")
+        );
+
+        std::fs::remove_file(format!("{}/b.txt", test_dir)).unwrap();
+        let event = recv(&rx);
+        assert!(matches!(&event, FileEvent::Removed(info) if info.file_name == "b.txt"));
+
+        // created and removed in a burst: nothing
+        std::fs::write(format!("{}/c.txt", test_dir), b"c").unwrap();
+        std::fs::remove_file(format!("{}/c.txt", test_dir)).unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+
+        drop(watcher);
+        std::fs::remove_dir_all(test_dir).unwrap();
+    }
+}
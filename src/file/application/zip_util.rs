@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::File,
     io::{BufReader, Read},
 };
@@ -7,7 +7,7 @@ use std::{
 use anyhow::Result;
 use zip::ZipArchive;
 
-use crate::file::{domain::zip_infos::ZipInfo, FileInfo, PathUtil};
+use crate::file::{domain::zip_infos::ZipInfo, sniff_zip_entry, FileInfo, PathUtil};
 
 pub struct ZipUtil {}
 
@@ -31,13 +31,14 @@ impl ZipUtil {
         // zip 形式では、dir はファイルとして存在しないことがあるため、infos から dir を補完する
         {
             let mut real_dirs: HashMap<String, ZipInfo> = HashMap::new();
-            let mut missing_dirs: HashMap<String, ZipInfo> = HashMap::new();
+            // 補完する dir の順序が毎回変わらないように BTreeMap を使う
+            let mut missing_dirs: BTreeMap<String, ZipInfo> = BTreeMap::new();
             for info in &mut infos {
                 if info.is_dir {
-                    real_dirs.insert(info.name.clone(), info.clone());
-                    if missing_dirs.contains_key(&info.name) {
-                        missing_dirs.remove(&info.name);
-                    }
+                    // 実在する dir は終端に / が付いているので外して比較する
+                    let name = info.name.remove_ends_separator();
+                    missing_dirs.remove(&name);
+                    real_dirs.insert(name, info.clone());
                 } else {
                     if info.name.contains('/') {
                         // 複数階層のディレクトリの場合があるため、分解して結合していく感じで補完
@@ -71,11 +72,23 @@ impl ZipUtil {
         let (archive, zip_infos) = Self::read(path)?;
         let infos = zip_infos
             .iter()
-            .map(|zip_info| FileInfo::from(zip_info))
+            .map(FileInfo::from)
             .collect::<Vec<FileInfo>>();
         Ok((archive, infos))
     }
 
+    /// Detect formats of entries from their contents. Entries that can not be detected keep the extension based flags.
+    pub fn sniff(buffer: &mut ZipArchive<BufReader<File>>, infos: &mut [FileInfo]) {
+        for info in infos.iter_mut().filter(|info| info.is_file) {
+            let Some(zip_info) = &info.zip_info else {
+                continue;
+            };
+            if let Ok(Some(format)) = sniff_zip_entry(buffer, &zip_info.name) {
+                info.set_format(format);
+            }
+        }
+    }
+
     pub fn read_bytes(
         buffer: &mut ZipArchive<BufReader<File>>,
         file_path: &str,
@@ -97,9 +110,13 @@ mod tests {
         let path = "tests/data/sample.zip";
 
         let (_, infos) = ZipUtil::read(path).unwrap();
-        assert!(infos.len() > 0);
+        assert!(!infos.is_empty());
+
+        let (mut archive, mut infos) = ZipUtil::read_file_infos(path).unwrap();
+        assert!(!infos.is_empty());
 
-        let (_, infos) = ZipUtil::read_file_infos(path).unwrap();
-        assert!(infos.len() > 0);
+        // empty text file: no format detected
+        ZipUtil::sniff(&mut archive, &mut infos);
+        assert!(infos.iter().all(|info| info.detected_format.is_none()));
     }
 }
@@ -0,0 +1,199 @@
+// file 系の tokio 版
+// 中身は同期版の関数を spawn_blocking で実行しているだけなので、振る舞いは同期版と同じ
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use zip::ZipArchive;
+
+use crate::file::zip_util::ZipUtil;
+use crate::file::{
+    FileEvent, FileInfo, FileWatcher, ReadDirOptions, WalkEntry, WalkOptions, WalkOrder,
+    WatchOptions, ZipInfo,
+};
+
+// number of entries buffered between the walker thread and the stream
+const STREAM_BUFFER: usize = 256;
+
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| anyhow!("Blocking task failed. {}", e))?
+}
+
+pub async fn read_dir(dir: &str) -> Result<Vec<FileInfo>> {
+    let dir = dir.to_string();
+    blocking(move || crate::file::read_dir(&dir)).await
+}
+
+pub async fn read_dir_with(dir: &str, options: ReadDirOptions) -> Result<Vec<FileInfo>> {
+    let dir = dir.to_string();
+    blocking(move || crate::file::read_dir_with(&dir, &options)).await
+}
+
+pub async fn read_dir_deep(dir: &str, deep: usize) -> Result<Vec<FileInfo>> {
+    let dir = dir.to_string();
+    blocking(move || crate::file::read_dir_deep(&dir, deep)).await
+}
+
+/// Stream version of `read_dir`. Must be called inside a tokio runtime (see `walk_stream`).
+pub fn read_dir_stream(dir: &str) -> impl Stream<Item = Result<FileInfo>> {
+    read_dir_deep_stream(dir, 1)
+}
+
+/// Stream version of `read_dir_deep`. Entries are sent as soon as they are found.
+/// Must be called inside a tokio runtime (see `walk_stream`).
+pub fn read_dir_deep_stream(dir: &str, deep: usize) -> impl Stream<Item = Result<FileInfo>> {
+    let options = WalkOptions {
+        max_depth: Some(deep),
+        order: WalkOrder::ListingFirst,
+        ..Default::default()
+    };
+    tokio_stream::StreamExt::map(walk_stream(dir, options), |entry| {
+        entry.map(|entry| entry.info)
+    })
+}
+
+/// Stream version of `walk_with`.
+/// The walk stops when the stream is dropped.
+///
+/// # Panics
+///
+/// Panics if called outside a tokio runtime (the walk runs on `spawn_blocking`).
+pub fn walk_stream(dir: &str, options: WalkOptions) -> impl Stream<Item = Result<WalkEntry>> {
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+    let dir = dir.to_string();
+    tokio::task::spawn_blocking(move || {
+        for entry in crate::file::walk_with(&dir, options) {
+            if tx.blocking_send(entry).is_err() {
+                // receiver dropped
+                break;
+            }
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+pub async fn rename(from: &str, to: &str) -> Result<()> {
+    let (from, to) = (from.to_string(), to.to_string());
+    blocking(move || crate::file::rename(&from, &to)).await
+}
+
+pub async fn remove_dir_all(path: &str) -> Result<()> {
+    let path = path.to_string();
+    blocking(move || crate::file::remove_dir_all(&path)).await
+}
+
+pub async fn write(path: PathBuf, data: Vec<u8>) -> Result<()> {
+    blocking(move || crate::file::write(path, &data)).await
+}
+
+pub async fn move_file(from: &str, to: &str) -> Result<()> {
+    let (from, to) = (from.to_string(), to.to_string());
+    blocking(move || crate::file::move_file(&from, &to)).await
+}
+
+/// Async version of `ZipUtil::read`.
+pub async fn read_zip(path: &str) -> Result<(ZipArchive<BufReader<File>>, Vec<ZipInfo>)> {
+    let path = path.to_string();
+    blocking(move || ZipUtil::read(&path)).await
+}
+
+/// Async version of `ZipUtil::read_file_infos`.
+pub async fn read_zip_file_infos(
+    path: &str,
+) -> Result<(ZipArchive<BufReader<File>>, Vec<FileInfo>)> {
+    let path = path.to_string();
+    blocking(move || ZipUtil::read_file_infos(&path)).await
+}
+
+/// Channel version of `watch`. Watching stops when the `FileWatcher` is dropped.
+pub fn watch(
+    dir: &str,
+    options: WatchOptions,
+) -> Result<(FileWatcher, mpsc::UnboundedReceiver<FileEvent>)> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let watcher = crate::file::watch(dir, options, move |event| {
+        let _ = tx.send(event);
+    })?;
+    Ok((watcher, rx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn test_async_read_dir() {
+        let test_dir = "test_async_read_dir";
+        std::fs::create_dir_all(format!("{}/sub", test_dir)).unwrap();
+
+        write(
+            PathBuf::from(format!("{}/file.txt", test_dir)),
+            b"test".to_vec(),
+        )
+        .await
+        .unwrap();
+        write(
+            PathBuf::from(format!("{}/sub/file.txt", test_dir)),
+            b"test".to_vec(),
+        )
+        .await
+        .unwrap();
+
+        let infos = read_dir(test_dir).await.unwrap();
+        assert_eq!(infos.len(), 2);
+
+        let infos: Vec<FileInfo> = read_dir_deep_stream(test_dir, 2)
+            .map(|info| info.unwrap())
+            .collect()
+            .await;
+        assert_eq!(infos.len(), 3);
+
+        move_file(
+            &format!("{}/file.txt", test_dir),
+            &format!("{}/moved/file.txt", test_dir),
+        )
+        .await
+        .unwrap();
+        assert!(crate::file::is_exists(&format!(
+            "{}/moved/file.txt",
+            test_dir
+        )));
+
+        remove_dir_all(test_dir).await.unwrap();
+        assert!(read_dir(test_dir).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_async_read_zip() {
+        let (_, infos) = read_zip_file_infos("tests/data/sample.zip").await.unwrap();
+        assert!(!infos.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_async_watch() {
+        let test_dir = "test_async_watch";
+        std::fs::create_dir_all(test_dir).unwrap();
+
+        let (watcher, mut rx) = watch(test_dir, WatchOptions::default()).unwrap();
+        std::fs::write(format!("{}/a.txt", test_dir), b"a").unwrap();
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, FileEvent::Created(_)));
+
+        drop(watcher);
+        remove_dir_all(test_dir).await.unwrap();
+    }
+}
@@ -1,4 +1,7 @@
+use std::path::Path;
+
 use crate::file::domain::file_info::FileInfo;
+use crate::file::{SortBy, SortUtil};
 use serde::{Deserialize, Serialize};
 
 // FileInfo の拡張情報をジェネリクスで持てるようにしたもの
@@ -38,3 +41,61 @@ impl<T: Default> From<FileInfo> for FileEntry<T> {
         }
     }
 }
+
+impl<T> FileEntry<T> {
+    /// Find entry by path from self and descendants
+    pub fn find(&self, path: &Path) -> Option<&FileEntry<T>> {
+        if self.info.path == path {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(path))
+    }
+
+    pub fn find_mut(&mut self, path: &Path) -> Option<&mut FileEntry<T>> {
+        if self.info.path == path {
+            return Some(self);
+        }
+        self.children
+            .iter_mut()
+            .find_map(|child| child.find_mut(path))
+    }
+
+    /// self and all descendants (pre-order)
+    pub fn flatten(&self) -> Vec<&FileEntry<T>> {
+        let mut entries = vec![self];
+        for child in &self.children {
+            entries.extend(child.flatten());
+        }
+        entries
+    }
+
+    /// Convert meta of all nodes
+    pub fn map<U>(self, f: &mut impl FnMut(&FileInfo, T) -> U) -> FileEntry<U> {
+        let meta = f(&self.info, self.meta);
+        FileEntry {
+            meta,
+            children: self
+                .children
+                .into_iter()
+                .map(|child| child.map(f))
+                .collect(),
+            info: self.info,
+        }
+    }
+
+    /// Remove descendants which `keep` returns false. Children of removed nodes are removed too.
+    pub fn prune(&mut self, keep: &impl Fn(&FileEntry<T>) -> bool) {
+        self.children.retain(|child| keep(child));
+        for child in &mut self.children {
+            child.prune(keep);
+        }
+    }
+
+    /// Sort children of all nodes
+    pub fn sort_children(&mut self, keys: &[SortBy]) {
+        self.children.sort_by_keys(keys);
+        for child in &mut self.children {
+            child.sort_children(keys);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+
+use crate::file::FileKind;
+
+// ファイルの中身 (マジックバイト) から判定した形式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileFormat {
+    Jpeg,
+    Png,
+    Gif,
+    WebP,
+    Bmp,
+    Tiff,
+    Avif,
+    Heic,
+    Mp4,
+    Mov,
+    Webm,
+    Mkv,
+    Avi,
+    Mpeg,
+    Zip,
+    Rar,
+    SevenZip,
+    Gzip,
+    Pdf,
+}
+
+impl FileFormat {
+    pub fn mime(&self) -> &'static str {
+        match self {
+            FileFormat::Jpeg => "image/jpeg",
+            FileFormat::Png => "image/png",
+            FileFormat::Gif => "image/gif",
+            FileFormat::WebP => "image/webp",
+            FileFormat::Bmp => "image/bmp",
+            FileFormat::Tiff => "image/tiff",
+            FileFormat::Avif => "image/avif",
+            FileFormat::Heic => "image/heic",
+            FileFormat::Mp4 => "video/mp4",
+            FileFormat::Mov => "video/quicktime",
+            FileFormat::Webm => "video/webm",
+            FileFormat::Mkv => "video/x-matroska",
+            FileFormat::Avi => "video/x-msvideo",
+            FileFormat::Mpeg => "video/mpeg",
+            FileFormat::Zip => "application/zip",
+            FileFormat::Rar => "application/vnd.rar",
+            FileFormat::SevenZip => "application/x-7z-compressed",
+            FileFormat::Gzip => "application/gzip",
+            FileFormat::Pdf => "application/pdf",
+        }
+    }
+
+    /// Typical extension (lowercase, without ".")
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FileFormat::Jpeg => "jpg",
+            FileFormat::Png => "png",
+            FileFormat::Gif => "gif",
+            FileFormat::WebP => "webp",
+            FileFormat::Bmp => "bmp",
+            FileFormat::Tiff => "tiff",
+            FileFormat::Avif => "avif",
+            FileFormat::Heic => "heic",
+            FileFormat::Mp4 => "mp4",
+            FileFormat::Mov => "mov",
+            FileFormat::Webm => "webm",
+            FileFormat::Mkv => "mkv",
+            FileFormat::Avi => "avi",
+            FileFormat::Mpeg => "mpg",
+            FileFormat::Zip => "zip",
+            FileFormat::Rar => "rar",
+            FileFormat::SevenZip => "7z",
+            FileFormat::Gzip => "gz",
+            FileFormat::Pdf => "pdf",
+        }
+    }
+
+    pub fn kind(&self) -> FileKind {
+        match self {
+            FileFormat::Jpeg
+            | FileFormat::Png
+            | FileFormat::Gif
+            | FileFormat::WebP
+            | FileFormat::Bmp
+            | FileFormat::Tiff
+            | FileFormat::Avif
+            | FileFormat::Heic => FileKind::Image,
+            FileFormat::Mp4
+            | FileFormat::Mov
+            | FileFormat::Webm
+            | FileFormat::Mkv
+            | FileFormat::Avi
+            | FileFormat::Mpeg => FileKind::Movie,
+            FileFormat::Zip | FileFormat::Rar | FileFormat::SevenZip | FileFormat::Gzip => {
+                FileKind::Archive
+            }
+            FileFormat::Pdf => FileKind::Document,
+        }
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.kind() == FileKind::Image
+    }
+
+    pub fn is_movie(&self) -> bool {
+        self.kind() == FileKind::Movie
+    }
+
+    pub fn is_zip(&self) -> bool {
+        *self == FileFormat::Zip
+    }
+}
@@ -1,12 +1,14 @@
 use serde::{Deserialize, Serialize};
-use std::fs::FileType;
 use std::path::Path;
 use std::{fs::DirEntry, path::PathBuf};
 
 use crate::file::domain::zip_infos::ZipInfo;
 use crate::file::path_util::PathUtil;
-use crate::file::{is_image, is_movie, FileMeta};
-use crate::file::{is_zip, OptionPathUtil};
+use crate::file::zip_util::ZipUtil;
+use crate::file::OptionPathUtil;
+use crate::file::{
+    is_zip_based, sniff_file, sniff_zip_entry, FileFormat, FileKind, FileKindRegistry, FileMeta,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileInfo {
@@ -19,9 +21,21 @@ pub struct FileInfo {
     pub is_dir: bool,
     pub is_file: bool,
     pub is_symlink: bool,
+    /// Target of the symlink (as written in the link)
+    #[serde(default)]
+    pub link_target: Option<PathBuf>,
+    /// Symlink whose target does not exist
+    #[serde(default)]
+    pub is_broken_link: bool,
+    /// Kind resolved from `FileKindRegistry`. is_image, is_movie and is_zip are derived from it.
+    #[serde(default)]
+    pub kind: FileKind,
     pub is_image: bool,
     pub is_movie: bool,
     pub is_zip: bool,
+    /// Format detected from the contents. Only set by `sniff()`
+    #[serde(default)]
+    pub detected_format: Option<FileFormat>,
 
     pub meta: Option<FileMeta>,
     pub zip_info: Option<ZipInfo>,
@@ -29,7 +43,7 @@ pub struct FileInfo {
 
 impl Default for FileInfo {
     fn default() -> Self {
-        let info = FileInfo {
+        FileInfo {
             path: PathBuf::new(),
             dir: PathBuf::new(),
             file_name: String::new(),
@@ -37,27 +51,48 @@ impl Default for FileInfo {
             is_dir: false,
             is_file: false,
             is_symlink: false,
+            link_target: None,
+            is_broken_link: false,
+            kind: FileKind::Other,
             is_image: false,
             is_movie: false,
             is_zip: false,
             zip_info: None,
+            detected_format: None,
             meta: None,
-        };
-        info
+        }
     }
 }
 
 impl From<DirEntry> for FileInfo {
     fn from(entry: DirEntry) -> Self {
+        // file_type が取れない場合はパスから (種類は推測)
+        FileInfo::from_entry(&entry).unwrap_or_else(|_| FileInfo::from_path(&entry.path()))
+    }
+}
+
+impl FileInfo {
+    /// From an entry of `fs::read_dir`. Fails when the file type can not be read.
+    pub fn from_entry(entry: &DirEntry) -> std::io::Result<Self> {
         let pathbuf = entry.path();
-        let file_type = entry.file_type().unwrap();
+        let file_type = entry.file_type()?;
         let ext = match pathbuf.extension() {
             Some(e) => e.to_string_ex().to_lowercase(),
             None => String::new(),
         };
-        let (is_dir, is_file, is_image, is_movie, is_zip) = file_type_to_info(file_type, &ext);
+        let (is_dir, is_file) = (file_type.is_dir(), file_type.is_file());
+        let (kind, is_image, is_movie, is_zip) = kind_to_info(is_dir, is_file, &ext);
+        let (link_target, is_broken_link) = if file_type.is_symlink() {
+            // NOTE: io cost (only for symlinks)
+            (
+                std::fs::read_link(&pathbuf).ok(),
+                std::fs::metadata(&pathbuf).is_err(),
+            )
+        } else {
+            (None, false)
+        };
 
-        FileInfo {
+        Ok(FileInfo {
             path: entry.path(),
             dir: pathbuf
                 .parent()
@@ -66,16 +101,20 @@ impl From<DirEntry> for FileInfo {
             file_name: pathbuf.file_name().to_string_ex(),
 
             extension: ext.clone(),
-            is_dir: is_dir,
-            is_file: is_file,
+            is_dir,
+            is_file,
             is_symlink: file_type.is_symlink(),
-            is_image: is_image,
-            is_movie: is_movie,
-            is_zip: is_zip,
+            link_target,
+            is_broken_link,
+            kind,
+            is_image,
+            is_movie,
+            is_zip,
             zip_info: None,
+            detected_format: None,
 
             meta: None,
-        }
+        })
     }
 }
 
@@ -83,19 +122,16 @@ impl From<&ZipInfo> for FileInfo {
     fn from(zip_info: &ZipInfo) -> Self {
         let pathbuf = PathBuf::from(&zip_info.full_path());
 
-        let (ext, is_image, is_movie, is_zip) = if zip_info.is_file {
-            let ext = match Path::new(&zip_info.name).extension() {
+        let ext = if zip_info.is_file {
+            match Path::new(&zip_info.name).extension() {
                 Some(e) => e.to_string_ex().to_lowercase(),
                 None => String::new(),
-            };
-            let is_image = is_image(&ext);
-            let is_movie = is_movie(&ext);
-            let is_zip = is_zip(&ext);
-
-            (ext, is_image, is_movie, is_zip)
+            }
         } else {
-            (String::new(), false, false, false)
+            String::new()
         };
+        let (kind, is_image, is_movie, is_zip) =
+            kind_to_info(zip_info.is_dir, zip_info.is_file, &ext);
 
         FileInfo {
             path: pathbuf.clone(),
@@ -109,10 +145,14 @@ impl From<&ZipInfo> for FileInfo {
             is_dir: zip_info.is_dir,
             is_file: zip_info.is_file,
             is_symlink: false,
-            is_image: is_image,
-            is_movie: is_movie,
-            is_zip: is_zip,
+            link_target: None,
+            is_broken_link: false,
+            kind,
+            is_image,
+            is_movie,
+            is_zip,
             zip_info: Some(zip_info.clone()),
+            detected_format: None,
 
             meta: None,
         }
@@ -125,20 +165,24 @@ impl From<&Path> for FileInfo {
     }
 }
 
-fn file_type_to_info(file_type: FileType, ext: &str) -> (bool, bool, bool, bool, bool) {
-    let is_file = file_type.is_file();
-    let is_dir = file_type.is_dir();
-
-    let (is_image, is_movie, is_zip) = if is_file {
-        let (is_image, is_movie) = { (is_image(ext), is_movie(ext)) };
-        let is_zip = is_zip(ext);
-
-        (is_image, is_movie, is_zip)
-    } else {
-        (false, false, false)
-    };
+// kind, is_image, is_movie, is_zip
+// is_image, is_movie, is_zip は互換のため kind から導出して残している
+fn kind_to_info(is_dir: bool, is_file: bool, ext: &str) -> (FileKind, bool, bool, bool) {
+    if is_dir {
+        return (FileKind::Dir, false, false, false);
+    }
+    if !is_file {
+        return (FileKind::Other, false, false, false);
+    }
 
-    (is_dir, is_file, is_image, is_movie, is_zip)
+    let (kind, is_zip) =
+        FileKindRegistry::with(|registry| (registry.kind_of(ext), registry.is_zip(ext)));
+    (
+        kind,
+        kind == FileKind::Image,
+        kind == FileKind::Movie,
+        is_zip,
+    )
 }
 
 impl FileInfo {
@@ -154,6 +198,7 @@ impl FileInfo {
         self.dir.to_string_ex()
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(path: &str) -> Self {
         let pathbuf = PathBuf::from(path);
         FileInfo::from_path(pathbuf.as_path())
@@ -172,9 +217,7 @@ impl FileInfo {
         let is_dir = is_entd_sep;
         let is_file = !is_dir;
 
-        let is_zip = is_zip(&ext);
-        let is_image = is_image(&ext);
-        let is_movie = is_movie(&ext);
+        let (kind, is_image, is_movie, is_zip) = kind_to_info(is_dir, is_file, &ext);
 
         // remove trailing separator for consistent path representation
         let new_path = if is_entd_sep {
@@ -198,8 +241,8 @@ impl FileInfo {
                 index: 0,
                 zip_path: zip_path.to_string(),
                 name: name.to_string(),
-                is_dir: is_dir,
-                is_file: is_file,
+                is_dir,
+                is_file,
                 size: 0,
             });
         }
@@ -207,20 +250,24 @@ impl FileInfo {
         FileInfo {
             path: new_path,
             // path: path.to_path_buf(),
-            dir: dir,
+            dir,
             file_name: path.file_name().to_string_ex(),
             extension: ext,
 
             is_dir,
             is_file,
             is_symlink: false, // 判別不可
+            link_target: None,
+            is_broken_link: false,
 
+            kind,
             is_image,
             is_movie,
             is_zip,
 
             meta: None,
-            zip_info: zip_info,
+            zip_info,
+            detected_format: None,
         }
     }
 
@@ -236,23 +283,58 @@ impl FileInfo {
 
         // get type from meta =====
         let file_type = meta.file_type();
-        let ext = match pathbuf.extension() {
-            Some(e) => e.to_string_ex().to_lowercase(),
-            None => String::new(),
-        };
-        (
-            self.is_dir,
-            self.is_file,
-            self.is_image,
-            self.is_movie,
-            self.is_zip,
-        ) = file_type_to_info(file_type, &ext);
+        self.set_type(file_type.is_dir(), file_type.is_file());
 
         // set loaded meta =====
         let meta = FileMeta::from(&meta);
         self.meta = Some(meta);
         self.clone()
     }
+
+    /// Set the detected format and kind/is_image/is_movie/is_zip from it.
+    /// When the format is unknown, the flags from the extension are kept. (IO cost)
+    pub fn sniff(&mut self) -> Self {
+        if !self.is_file {
+            return self.clone();
+        }
+
+        let format = match &self.zip_info {
+            Some(zip_info) => ZipUtil::open(&zip_info.zip_path)
+                .and_then(|mut archive| sniff_zip_entry(&mut archive, &zip_info.name)),
+            None => sniff_file(&self.path_string()),
+        };
+        match format {
+            // docx, epub, ... は zip だが文書
+            Ok(Some(FileFormat::Zip)) if is_zip_based(&self.extension) => {}
+            Ok(Some(format)) => self.set_format(format),
+            _ => {}
+        }
+        self.clone()
+    }
+
+    /// Set is_dir/is_file and kind/is_image/is_movie/is_zip from them (e.g. the resolved target of a symlink)
+    pub fn set_type(&mut self, is_dir: bool, is_file: bool) {
+        self.is_dir = is_dir;
+        self.is_file = is_file;
+        (self.kind, self.is_image, self.is_movie, self.is_zip) =
+            kind_to_info(is_dir, is_file, &self.extension);
+        if let Some(format) = self.detected_format {
+            self.set_format(format);
+        }
+    }
+
+    pub fn set_format(&mut self, format: FileFormat) {
+        self.detected_format = Some(format);
+        self.kind = format.kind();
+        self.is_image = self.kind == FileKind::Image;
+        self.is_movie = self.kind == FileKind::Movie;
+        self.is_zip = format.is_zip();
+    }
+
+    /// MIME type of the detected format
+    pub fn mime(&self) -> Option<&'static str> {
+        self.detected_format.map(|format| format.mime())
+    }
 }
 
 // test
@@ -277,15 +359,15 @@ mod tests {
 
         assert_eq!(file_info.file_name, "image1.jpg");
         assert_eq!(file_info.extension, "jpg");
-        assert_eq!(file_info.is_file, true);
-        assert_eq!(file_info.is_image, true);
-        assert_eq!(file_info.is_movie, false);
-        assert_eq!(file_info.is_dir, false);
-        assert_eq!(file_info.is_zip, false);
-        assert_eq!(file_info.zip_info.is_none(), true);
+        assert!(file_info.is_file);
+        assert!(file_info.is_image);
+        assert!(!file_info.is_movie);
+        assert!(!file_info.is_dir);
+        assert!(!file_info.is_zip);
+        assert!(file_info.zip_info.is_none());
         assert_eq!(file_info.path_string(), "test_data/image1.jpg");
         assert_eq!(file_info.dir_string(), "test_data");
-        assert_eq!(file_info.meta.is_some(), true);
+        assert!(file_info.meta.is_some());
 
         // clean up
         std::fs::remove_file(format!("{}/{}", test_dir, test_file)).unwrap();
@@ -305,15 +387,15 @@ mod tests {
 
         assert_eq!(file_info.file_name, test_dir_base);
         assert_eq!(file_info.extension, "");
-        assert_eq!(file_info.is_file, false);
-        assert_eq!(file_info.is_image, false);
-        assert_eq!(file_info.is_movie, false);
-        assert_eq!(file_info.is_dir, true);
-        assert_eq!(file_info.zip_info.is_none(), true);
+        assert!(!file_info.is_file);
+        assert!(!file_info.is_image);
+        assert!(!file_info.is_movie);
+        assert!(file_info.is_dir);
+        assert!(file_info.zip_info.is_none());
         assert_eq!(file_info.path_string(), test_dir_base);
         assert_eq!(file_info.dir_string(), "");
-        assert_eq!(file_info.meta.is_none(), true);
-        assert_eq!(file_info.is_zip, false);
+        assert!(file_info.meta.is_none());
+        assert!(!file_info.is_zip);
 
         // clean up
         std::fs::remove_dir(test_dir).unwrap();
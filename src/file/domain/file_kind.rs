@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+// 拡張子 (または中身) から判定したファイルの種類
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FileKind {
+    Dir,
+    Image,
+    Movie,
+    Audio,
+    Document,
+    Text,
+    Archive,
+    Subtitle,
+    #[default]
+    Other,
+}
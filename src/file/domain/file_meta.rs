@@ -1,4 +1,8 @@
-use std::{fs::Metadata, path::Path};
+use std::{
+    fs::Metadata,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -6,21 +10,38 @@ use crate::time::Timestamp;
 
 // get meta infor from fs::Metadata
 // because only one IO operation per Metadata fetch,
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// 追加した項目は serde(default) (古い JSON も読める)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct FileMeta {
     pub modified: u64, // Timestamp
     pub created: u64,  // Timestamp
     pub size: u64,
-}
-
-impl Default for FileMeta {
-    fn default() -> Self {
-        FileMeta {
-            modified: 0,
-            created: 0,
-            size: 0,
-        }
-    }
+    #[serde(default)]
+    pub accessed: u64, // Timestamp
+    /// Sub-second part of `modified` / `created` / `accessed` (nanoseconds)
+    #[serde(default)]
+    pub modified_nsec: u32,
+    #[serde(default)]
+    pub created_nsec: u32,
+    #[serde(default)]
+    pub accessed_nsec: u32,
+    /// Unix file type and permission bits (`st_mode`). 0 on Windows.
+    #[serde(default)]
+    pub mode: u32,
+    #[serde(default)]
+    pub uid: u32,
+    #[serde(default)]
+    pub gid: u32,
+    /// Inode and device. The same pair means the same file (hard links). 0 on Windows.
+    #[serde(default)]
+    pub ino: u64,
+    #[serde(default)]
+    pub dev: u64,
+    /// Number of hard links
+    #[serde(default)]
+    pub nlink: u64,
+    #[serde(default)]
+    pub readonly: bool,
 }
 
 impl From<&Path> for FileMeta {
@@ -35,16 +56,98 @@ impl From<&Path> for FileMeta {
 
 impl From<&Metadata> for FileMeta {
     fn from(meta: &Metadata) -> Self {
-        let modified = meta
-            .modified()
-            .map(Timestamp::from_system_time)
-            .unwrap_or(0);
-        let created = meta.created().map(Timestamp::from_system_time).unwrap_or(0);
+        let (modified, modified_nsec) = split_time(meta.modified());
+        let (created, created_nsec) = split_time(meta.created());
+        let (accessed, accessed_nsec) = split_time(meta.accessed());
 
-        FileMeta {
+        let file_meta = FileMeta {
             modified,
             created,
             size: meta.len(),
+            accessed,
+            modified_nsec,
+            created_nsec,
+            accessed_nsec,
+            readonly: meta.permissions().readonly(),
+            ..Default::default()
+        };
+        #[cfg(unix)]
+        let file_meta = {
+            use std::os::unix::fs::MetadataExt;
+            FileMeta {
+                mode: meta.mode(),
+                uid: meta.uid(),
+                gid: meta.gid(),
+                ino: meta.ino(),
+                dev: meta.dev(),
+                nlink: meta.nlink(),
+                ..file_meta
+            }
+        };
+        file_meta
+    }
+}
+
+impl FileMeta {
+    /// `modified` with nanoseconds
+    pub fn modified_time(&self) -> SystemTime {
+        to_system_time(self.modified, self.modified_nsec)
+    }
+
+    pub fn created_time(&self) -> SystemTime {
+        to_system_time(self.created, self.created_nsec)
+    }
+
+    pub fn accessed_time(&self) -> SystemTime {
+        to_system_time(self.accessed, self.accessed_nsec)
+    }
+}
+
+// (秒, ナノ秒) 取れない場合は 0
+fn split_time(time: std::io::Result<SystemTime>) -> (u64, u32) {
+    match time {
+        Ok(time) => (
+            Timestamp::from_system_time(time),
+            time.duration_since(UNIX_EPOCH)
+                .map(|d| d.subsec_nanos())
+                .unwrap_or(0),
+        ),
+        Err(_) => (0, 0),
+    }
+}
+
+fn to_system_time(secs: u64, nsec: u32) -> SystemTime {
+    UNIX_EPOCH + std::time::Duration::new(secs, nsec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_meta() {
+        let path = "test_file_meta.txt";
+        std::fs::write(path, b"meta").unwrap();
+        let meta = std::fs::metadata(path).unwrap();
+        let file_meta = FileMeta::from(Path::new(path));
+
+        assert_eq!(file_meta.size, 4);
+        assert_eq!(file_meta.modified_time(), meta.modified().unwrap());
+        assert!(file_meta.accessed > 0);
+        assert!(!file_meta.readonly);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(file_meta.mode & 0o170000, 0o100000); // regular file
+            assert_eq!((file_meta.ino, file_meta.dev), (meta.ino(), meta.dev()));
+            assert_eq!(file_meta.nlink, 1);
+            assert_eq!(file_meta.uid, meta.uid());
         }
+
+        // 以前の形式の JSON
+        let old: FileMeta = serde_json::from_str(r#"{"modified":1,"created":2,"size":3}"#).unwrap();
+        assert_eq!((old.modified, old.accessed, old.mode), (1, 0, 0));
+
+        std::fs::remove_file(path).unwrap();
     }
 }
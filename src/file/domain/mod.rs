@@ -1,4 +1,6 @@
 pub(crate) mod file_entry;
+pub(crate) mod file_format;
 pub(crate) mod file_info;
+pub(crate) mod file_kind;
 pub(crate) mod file_meta;
 pub(crate) mod zip_infos;
@@ -1,4 +1,5 @@
 pub(crate) mod application;
+pub mod r#async;
 pub(crate) mod domain;
 pub(crate) mod path_util;
 pub mod prelude;
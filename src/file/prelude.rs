@@ -1,7 +1,9 @@
 pub use crate::file::application::*;
 
 pub use crate::file::domain::file_entry::*;
+pub use crate::file::domain::file_format::*;
 pub use crate::file::domain::file_info::*;
+pub use crate::file::domain::file_kind::*;
 pub use crate::file::domain::file_meta::*;
 pub use crate::file::domain::zip_infos::*;
 pub use crate::file::path_util::*;
@@ -36,7 +36,7 @@ pub const A3: PaperSize = PaperSize { x: 297, y: 420 };
 pub const A4: PaperSize = PaperSize { x: 210, y: 297 };
 
 // f64::sqrt()
-const PAPER_RATE: f64 = 1.4142135623730951;
+const PAPER_RATE: f64 = std::f64::consts::SQRT_2;
 
 pub fn create(w: u32, h: u32, path: &str) -> ImageResult<()> {
     let mut img = ImageBuffer::new(w, h);
@@ -103,14 +103,14 @@ pub fn crop_center(img: &DynamicImage, width: u32, height: u32) -> DynamicImage
     let (w, h) = img.dimensions();
 
     let (_w, _x) = if w > width {
-        (width, ((w - width) as f64 / 2 as f64) as u32)
+        (width, ((w - width) as f64 / 2_f64) as u32)
     } else {
         // 実際のサイズを超えた指定なので、フル指定
         (w, 0)
     };
 
     let (_h, _y) = if h > height {
-        (height, ((h - height) as f64 / 2 as f64) as u32)
+        (height, ((h - height) as f64 / 2_f64) as u32)
     } else {
         // 実際のサイズを超えた指定なので、フル指定
         (h, 0)
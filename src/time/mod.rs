@@ -4,6 +4,68 @@ pub struct Timestamp {}
 
 impl Timestamp {
     pub fn from_system_time(system_time: SystemTime) -> u64 {
-        system_time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+        system_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
     }
-}
\ No newline at end of file
+
+    // Timestamp (秒) -> YYYY-MM-DDThh:mm:ss (UTC)
+    pub fn to_utc_string(timestamp: u64) -> String {
+        let days = (timestamp / 86400) as i64;
+        let secs = timestamp % 86400;
+        // civil from days (Howard Hinnant)
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )
+    }
+
+    // Timestamp (秒) -> YYYY-MM-DDThh:mm:ss (local time, タイムゾーンなし)
+    pub fn to_local_string(timestamp: u64) -> String {
+        Timestamp::to_utc_string(timestamp.saturating_add_signed(local_offset(timestamp)))
+    }
+}
+
+// UTC からのずれ (秒)
+#[cfg(unix)]
+fn local_offset(timestamp: u64) -> i64 {
+    let time = timestamp as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // localtime_r はスレッドセーフ
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff as i64
+}
+
+// unix 以外は UTC のまま
+#[cfg(not(unix))]
+fn local_offset(_timestamp: u64) -> i64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(Timestamp::to_utc_string(0), "1970-01-01T00:00:00");
+        assert_eq!(Timestamp::to_utc_string(1093991528), "2004-08-31T22:32:08");
+    }
+}